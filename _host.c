@@ -30,6 +30,23 @@ typedef signed char s8;
 int line_no;
 # endif /* M68K_DEBUG */
 
+/* the last hostcalls made, so a crash report can tell where we came from */
+#define HCALL_TRACE_LEN	32
+
+struct HcallTrace {
+	int num;
+	int line;
+};
+
+struct HcallTrace hcall_trace[HCALL_TRACE_LEN];
+unsigned int hcall_trace_pos;
+
+#define HCALL_TRACE(n, l) do { \
+	hcall_trace[hcall_trace_pos % HCALL_TRACE_LEN].num = (n); \
+	hcall_trace[hcall_trace_pos % HCALL_TRACE_LEN].line = (l); \
+	hcall_trace_pos++; \
+} while (0)
+
 #define MEM_SIZE	(0x110000)
 
 union Reg {
@@ -1742,6 +1742,7 @@ void c_func_illegal ()
 void c_func_hcall (int val)
 {
 	if (gen_mode != GEN_CALL) return;
+	cln ("HCALL_TRACE (%d, %d);", val, line_no);
 	cln ("(* hcalls[%d]) ();", val);
 }
 
@@ -35,6 +35,7 @@ Call_Fdelete		equ	$24
 Call_Fopendir		equ	$25
 Call_Freaddir		equ	$26
 Call_Fclosedir		equ	$27
+Call_PlaySFXEx		equ	$28
 Nu_PutTriangle		equ	$60
 Nu_PutQuad		equ	$61
 Nu_PutLine		equ	$62
@@ -250,6 +251,12 @@ L22_PlayEffect:
 		hcall	#Call_PlaySFX
 		rts
 
+* play an effect (d0) with pan (d2, -256 left to 256 right) and
+* distance (d3, 0 to 63) on one of the voices kept for positioned sounds
+play_effect_at:
+		hcall	#Call_PlaySFXEx
+		rts
+
 L54_StopMusic:
 		hcall	#Call_StopMusic
 		rts
@@ -263,7 +270,8 @@ L6e_rts:
 L70_StartMusic:
 		* d0:
 		* -1 = random
-		* -2 = fuck knows
+		* -2 = random once, battle
+		* -3 = random once, hyperspace (-2 in the original)
 		* >=0 = index
 		movem.l	d1-2,-(a7)
 		movem.l	A6_opt_selected_music(a6),d1-2
@@ -18184,8 +18192,13 @@ L3e036:
 		rts
 
 L3e05c_SFX_Thing:
+		* an object makes a sound, effect in d6. -16(a6) is where it
+		* is relative to the player. the original passed 63-distance
+		* as the channel, meant as a volume
 		lsr.w	#5,d6
+		movem.l	d3-4,-(a7)
 		movem.l	-16(a6),d0-2
+		move.l	d0,d3
 		move.l	d0,d0
 		bpl.s	l3e06a
 		neg.l	d0
@@ -18195,21 +18208,36 @@ L3e05c_SFX_Thing:
 	l3e070:	move.l	d2,d2
 		bpl.s	l3e076
 		neg.l	d2
-	l3e076:	add.l	d1,d0
+	l3e076:	move.l	d0,d4
+		add.l	d1,d0
 		add.l	d2,d0
 		lsr.l	#1,d0
 		swap	d0
 		cmp.w	#$3f,d0
 		bcc.s	l3e09c
-		moveq	#63,d1
-		sub.w	d0,d1
+		* pan = 256*x/(|x|+|z|), scaled down for divs first
+		add.l	d2,d4
+	lsfxscale:	cmp.l	#$7fff,d4
+		bls.s	lsfxpan
+		asr.l	#1,d3
+		lsr.l	#1,d4
+		bra.s	lsfxscale
+	lsfxpan:	asl.l	#8,d3
+		tst.w	d4
+		beq.s	lsfxplay
+		divs	d4,d3
+	lsfxplay:	move.w	d3,d2
+		ext.l	d2
+		moveq	#0,d3
+		move.w	d0,d3
 		move.w	d6,d0
 		ext.l	d0
 		move.l	a6,-(a7)
 		lea	L5eb6_a6_base,a6
-		jsr	L22_PlayEffect
+		jsr	play_effect_at
 		movea.l	(a7)+,a6
-	l3e09c:	rts
+	l3e09c:	movem.l	(a7)+,d3-4
+		rts
 
 		addq.l	#2,a5
 		addq.l	#2,a5
@@ -42096,7 +42124,7 @@ L63362:
 	l6343e:	move.b	A6_opt_music_on(a6),d0
 		and.b	A6_opt_music_hyperspace(a6),d0
 		beq.s	l6348c
-		moveq	#-2,d0
+		moveq	#-3,d0
 		jmp	A5_StartMusic(a5)
 	l6344e:	moveq	#9,d0
 		movea.l	A6_mod_police(a6),a2
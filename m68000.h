@@ -18,6 +18,16 @@ extern HOSTCALL hcalls [];
 extern char *STRam;
 extern int line_no;
 
+#define HCALL_TRACE_LEN	32
+
+struct HcallTrace {
+	int num;
+	int line;
+};
+
+extern struct HcallTrace hcall_trace[HCALL_TRACE_LEN];
+extern unsigned int hcall_trace_pos;
+
 extern int Init680x0 ();
 extern void Start680x0 ();
 extern void FlagException (int num);
@@ -6,6 +6,7 @@
 extern BOOL bDisableSound;
 
 extern void Call_PlaySFX ();
+extern void Call_PlaySFXEx ();
 extern void Call_PlayMusic ();
 extern void Call_StopMusic ();
 extern void Call_IsMusicPlaying ();
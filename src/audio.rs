@@ -1,432 +1,576 @@
-use std::fs::File;
 use std::cell::RefCell;
-use std::ops::DerefMut;
+use std::ops::{ DerefMut, Range };
+use std::time::Duration;
 
-use rand::Rng;
-use lewton::inside_ogg::OggStreamReader;
-use sdl2::audio::{
-    AudioCallback, AudioSpecWAV, AudioFormat, AudioDevice, AudioFormatNum
-};
+use sdl2::AudioSubsystem;
+use sdl2::audio::{ AudioSpecDesired, AudioSpecWAV, AudioFormat };
 
 use crate::{ SdlContext, GameConfig };
-
-type StaticAudioDeviceRef = RefCell<Option<AudioDevice<Callback>>>;
+use crate::error::{ Error, Result };
+use crate::config;
+use crate::paths;
+
+mod backend;
+mod engine;
+mod mixer;
+mod music;
+mod playlist;
+mod resample;
+mod ring;
+mod sink;
+mod soundpack;
+mod soundtrack;
+
+use backend::{ Backend, SdlBackend };
+use engine::{ Engine, Format, WavStream };
+use mixer::Placement;
+use music::Music;
+use sink::{ Sink, WavWriter };
+use soundpack::{ LoopPoints, SoundPack };
+
+type StaticAudioOutputRef = RefCell<Option<Box<dyn Backend>>>;
+type StaticHotplugRef = RefCell<Option<Hotplug>>;
+type StaticMusicRef = RefCell<Option<Music>>;
 
 
 /* Converted frontier SFX to wav samples. */
 static MAX_SAMPLES: i32	= 33;
-static SND_FREQ: i32 = 22050;
-static MAX_CHANNELS: usize = 4;
+
+/* the game doesn't play sounds of objects this far away */
+static SFX_MAX_DISTANCE: u16 = 64;
 
 thread_local! {
-    static AUDIO_DEVICE: StaticAudioDeviceRef = RefCell::default();
+    static AUDIO_OUTPUT: StaticAudioOutputRef = RefCell::default();
+    static MUSIC: StaticMusicRef = RefCell::default();
+    static HOTPLUG: StaticHotplugRef = RefCell::default();
+    static VOLUME: RefCell<Volume> = RefCell::default();
 }
 
-#[derive(Clone, PartialEq)]
-struct WavStream {
-    buffer: Vec<u8>,
-    should_loop: i32, // -1 no loop, otherwise specifies loop start pos
-}
+static MAX_VOLUME: u8 = 100;
 
-impl WavStream {
-    fn new(buffer: Vec<u8>, should_loop: i32) -> Self {
-        Self {
-            buffer,
-            should_loop,
-        }
-    }
-}
+/* used by the sink when the config leaves the rate to SDL */
+static SINK_FREQ: i32 = 48000;
 
-#[derive(Clone)]
-struct WavChannel {
-    buffer_pos: usize,
-    should_loop: i32,
-    buffer_ref: usize
+#[derive(Clone, Copy, Debug)]
+pub enum VolumeControl {
+    Master,
+    Music,
+    Sfx,
 }
 
-impl WavChannel {
-    fn buffer_pos(&self) -> usize {
-        self.buffer_pos
-    }
-
-    fn set_buffer_pos(&mut self, pos: usize) {
-        self.buffer_pos = pos;
-    }
-
-    fn should_loop(&self) -> i32 {
-        self.should_loop
-    }
-
-    fn buffer<'a>(&self, context: &'a Callback) -> &'a Vec<u8> {
-        &context.sfx_list[self.buffer_ref].buffer
-    }
-
-    fn from_stream(context: &[WavStream], stream: &WavStream) -> Self {
-        let index = context.iter().position(|item| item == stream).expect("Context doees not contain stream!");
-
-        Self {
-            buffer_pos: 0,
-            should_loop: stream.should_loop,
-            buffer_ref: index
+impl VolumeControl {
+    pub fn name(self) -> &'static str {
+        match self {
+            VolumeControl::Master => "Volume",
+            VolumeControl::Music => "Music volume",
+            VolumeControl::Sfx => "Sound effects volume",
         }
     }
 }
 
-struct Callback {
-    wav_channels: Vec<Option<WavChannel>>,
-    sfx_list: Vec<WavStream>,
-    music_file: Option<OggStreamReader<File>>,
-    enabled_tracks: usize,
-    music_mode: isize,
+/* levels in percent, as in the config */
+#[derive(Clone, Copy)]
+struct Volume {
+    master: u8,
+    music: u8,
+    sfx: u8,
 }
 
-impl Callback {
-    fn new(sfx_list: Vec<WavStream>) -> Self {
-        let wav_channels = vec![None; MAX_CHANNELS];
+impl Default for Volume {
+    fn default() -> Self {
+        Self { master: MAX_VOLUME, music: MAX_VOLUME, sfx: MAX_VOLUME }
+    }
+}
 
+impl Volume {
+    fn from_config(config: &GameConfig) -> Self {
         Self {
-            wav_channels,
-            sfx_list,
-            music_file: None,
-            enabled_tracks: 0,
-            music_mode: 0,
+            master: config.master_volume.min(MAX_VOLUME),
+            music: config.music_volume.min(MAX_VOLUME),
+            sfx: config.sfx_volume.min(MAX_VOLUME),
         }
     }
 
-    fn set_wav_channel(&mut self, channel: usize, wav_channel: WavChannel) {
-        self.wav_channels[channel] = Some(wav_channel)
-    }
-
-    fn wav_channels(&self) -> Vec<Option<&WavChannel>> {
-        self.wav_channels.iter()
-            .map(|option| option.as_ref())
-            .collect()
-    }
-
-    fn wav_channels_mut(&mut self) -> Vec<Option<&mut WavChannel>> {
-        self.wav_channels.iter_mut()
-            .map(|option| option.as_mut())
-            .collect()
+    fn level_mut(&mut self, control: VolumeControl) -> &mut u8 {
+        match control {
+            VolumeControl::Master => &mut self.master,
+            VolumeControl::Music => &mut self.music,
+            VolumeControl::Sfx => &mut self.sfx,
+        }
     }
+}
 
-    fn clear_wav_channel(&mut self, channel: usize) {
-        self.wav_channels[channel] = None;
-    }
+fn gain(level: u8) -> f32 {
+    level as f32 / MAX_VOLUME as f32
 }
 
-trait Reset<T> {
-    fn reset(&mut self, value: Option<T>);
+/* what it takes to move the mixer to another device */
+struct Hotplug {
+    audio: AudioSubsystem,
+    /* from the config, None is SDL's default device */
+    wanted: Option<String>,
+    /* the one we play on, None for the default device or no device at all */
+    current: Option<String>,
+    /* the configured device if it didn't open, it is left alone until a device goes away */
+    failed: Option<String>,
+    /* SDL announces the devices that were there at startup as added, we already chose from them */
+    startup_devices: u32,
+    announced: u32,
+    enabled: bool,
 }
 
-impl Reset<i16> for [i16] {
-    fn reset(&mut self, value: Option<i16>) {
-        for byte in self.iter_mut() {
-            *byte = value.unwrap_or(0);
+impl Hotplug {
+    fn new(audio: &AudioSubsystem, config: &GameConfig) -> Self {
+        Self {
+            audio: audio.clone(),
+            wanted: config.audio_device.clone(),
+            current: None,
+            failed: None,
+            startup_devices: audio.num_audio_playback_devices().unwrap_or(0),
+            announced: 0,
+            enabled: true,
         }
     }
-}
 
+    fn set_current(&mut self, name: Option<String>) {
+        if self.wanted.is_some() && name != self.wanted {
+            self.failed = self.wanted.clone();
+        }
 
-impl AudioCallback for Callback {
-    type Channel = i16; /* 8 Bit unsigned audio format */
-
-    fn callback(&mut self, dest_buffer: &mut [Self::Channel]) {
-        let playing = self.wav_channels.iter().any(|channel| channel.is_some());
+        self.current = name;
+    }
 
-        dest_buffer.reset(Some(Self::Channel::SILENCE));
+    /* moves the engine to a device, or into a sink if there is none */
+    fn reopen(&mut self, engine: Engine) -> Box<dyn Backend> {
+        let format = engine.format();
+        let desired_spec = AudioSpecDesired {
+            freq: Some(format.freq),
+            channels: Some(format.channels),
+            samples: Some(format.samples),
+        };
+        let mut engine = Some(engine);
 
-        if let Some(ref mut music_file) = self.music_file {
-            let mut i = 0;
+        /* the engine is only taken once a device has been opened */
+        let opened = SdlBackend::open(&self.audio, self.wanted.as_deref(), &desired_spec, |_| {
+            engine.take().expect("engine moved twice")
+        });
 
-            while i < dest_buffer.len() {
-                let sample = music_file.read_dec_packet_itl().expect("vorbis read");
+        let mut output: Box<dyn Backend> = match opened {
+            Ok((backend, name)) => {
+                info!("now playing on {}", name.as_deref().unwrap_or("the default device"));
 
-                if let Some(sample) = sample {
-                    dest_buffer[i..(i + sample.len())].copy_from_slice(&sample);
+                self.set_current(name);
+                Box::new(backend)
+            },
+            Err(error) => {
+                warn!("{}, mixing without a device until one is plugged in", error);
 
-                    i += sample.len();
+                self.set_current(None);
+                Box::new(Sink::new(engine.take().expect("engine lost"), None))
+            },
+        };
 
-                    continue;
-                }
+        output.set_paused(!self.enabled);
+        output
+    }
+}
 
-                /* end of stream */
-                println!("ogg stream ended.");
+pub fn init(context: &SdlContext, config: &GameConfig) {
+    /* kept even without sound, so the hotkeys still work on the config */
+    VOLUME.with(|volume_ref_cell| {
+        *volume_ref_cell.borrow_mut() = Volume::from_config(config);
+    });
 
-                if self.music_mode == -1 {
-                    play_music_track(self, rand_tracknum(self));
-                    break;
-                }
+    /* Is enabled? */
+    if config.nosound {
+        /* Stop any sound access */
+        info!("disabled");
+        return;
+    }
 
-                stop_music_track(self);
-                break;
-            }
-        }
+    /* without a device the game still gets working sfx and music, nobody hears them */
+    let opened = match context.audio() {
+        _ if config.headless || config.audio_out.is_some() => open_sink(config),
+        None => {
+            warn!("SDL audio not available, mixing without a device");
+            open_sink(config)
+        },
+        Some(audio) => {
+            let mut hotplug = Hotplug::new(audio, config);
+            let opened = open_device(&mut hotplug, config).or_else(|error| {
+                warn!("{}, mixing without a device", error);
+                open_sink(config)
+            });
+
+            HOTPLUG.with(|hotplug_ref_cell| {
+                *hotplug_ref_cell.borrow_mut() = Some(hotplug);
+            });
+
+            opened
+        },
+    };
 
-        if !playing {
+    let (mut output, music) = match opened {
+        Ok(opened) => opened,
+        Err(error) => {
+            warn!("{}, sound disabled", error);
             return;
         }
+    };
 
-        for i in (0..dest_buffer.len()).step_by(2) {
-            let mut sample: i16 = 0;
-
-            for j in 0..MAX_CHANNELS {
-                let wav_channels = self.wav_channels();
+    /* And begin */
+    output.set_paused(false);
 
-                if wav_channels[j].is_none() {
-                    continue;
-                }
+    AUDIO_OUTPUT.with(|audio_output_ref_cell| {
+        *audio_output_ref_cell.borrow_mut() = Some(output);
+    });
 
-                let channel = wav_channels[j].unwrap();
-                let buffer = channel.buffer(self);
-                let buffer_pos = channel.buffer_pos();
-                let buffer_len = buffer.len();
+    MUSIC.with(|music_ref_cell| {
+        *music_ref_cell.borrow_mut() = Some(music);
+    });
+}
 
-                let byte_a = buffer[buffer_pos] as u16;
-                let byte_b = buffer[buffer_pos+1] as u16;
+pub fn list_devices(audio: &sdl2::AudioSubsystem) -> Result<Vec<String>> {
+    let num_audio_devices = audio.num_audio_playback_devices()
+        .ok_or_else(|| Error::Audio("not available".into()))?;
 
-                sample += (byte_a | byte_b << 8) as i16;
+    (0..num_audio_devices)
+        .map(|index| audio.audio_playback_device_name(index).map_err(|error| Error::Audio(error.to_string())))
+        .collect()
+}
 
-                { // create scope to contain mutable ref
-                    let mut wav_channels = self.wav_channels_mut();
-                    let mut channel = wav_channels[j].as_mut().unwrap();
+fn open_device(hotplug: &mut Hotplug, config: &GameConfig) -> Result<(Box<dyn Backend>, Music)> {
+    let audio = &hotplug.audio;
+    let num_audio_devices = audio.num_audio_playback_devices()
+        .ok_or_else(|| Error::Audio("not available".into()))?;
 
-                    channel.set_buffer_pos(buffer_pos + 2);
+    if num_audio_devices == 0 {
+        return Err(Error::Audio("no audio device found".into()));
+    }
 
-                    if channel.buffer_pos() < buffer_len {
-                        continue;
-                    }
+    /*
+     * Set up SDL audio. A rate of 0 leaves it to SDL. The device is opened
+     * without allowing changes, so we mix at the rate asked for and SDL
+     * converts to the hardware's if it has to. The mixer always writes stereo.
+     */
+    let desired_spec = AudioSpecDesired {
+        freq: Some(config.audio_frequency).filter(|&freq| freq > 0),
+        channels: Some(2),
+        samples: Some(config.audio_buffer), /* Buffer size */
+    };
 
-                    /* end of sample. either loop or terminate */
-                    if channel.should_loop() != -1 {
-                        channel.buffer_pos = channel.should_loop as usize;
-                        continue;
-                    }
-                }
+    let mut music_ring = None;
 
-                self.clear_wav_channel(j);
-            }
+    let (backend, name) = SdlBackend::open(audio, hotplug.wanted.as_deref(), &desired_spec, |format| {
+        info!("opened {}Hz, {} channels, {} samples buffer ({:.1}ms)", format.freq, format.channels,
+            format.samples, format.samples as f32 * 1000.0 / format.freq.max(1) as f32);
 
-            /* stereo! */
-            dest_buffer[i] += sample;
-            dest_buffer[i+1] += sample;
-        }
-    }
-}
+        let (engine, producer) = create_engine(format, config);
 
-pub fn init(context: &SdlContext, config: &GameConfig) {
-    /* Is enabled? */
-    if config.nosound {
-        /* Stop any sound access */
-        println!("Sound: Disabled");
-        return;
-    }
+        music_ring = Some((producer, format.freq));
+        engine
+    })?;
 
-    let mut sfx_list = vec!();
-    let num_audio_devices = context.audio().num_audio_playback_devices();
+    info!("playing on {}", name.as_deref().unwrap_or("the default device"));
+    hotplug.set_current(name);
 
-    if num_audio_devices.is_none() {
-        println!("Sound: Disabled, not available!");
-        return;
-    }
+    let (producer, freq) = music_ring
+        .ok_or_else(|| Error::Audio("device opened without a spec".into()))?;
+    let music = Music::start(paths::data_file("music"), freq, producer, config)?;
 
-    let num_audio_devices = num_audio_devices.unwrap();
+    Ok((Box::new(backend), music))
+}
 
-    if num_audio_devices == 0 {
-        println!("Sound: Disabled, no audio device found!");
-        return;
-    }
+fn open_sink(config: &GameConfig) -> Result<(Box<dyn Backend>, Music)> {
+    let freq = if config.audio_frequency > 0 { config.audio_frequency } else { SINK_FREQ };
+    let format = Format { freq, channels: 2, samples: config.audio_buffer };
 
-    /* Set up SDL audio: */
-    let desired_spec = sdl2::audio::AudioSpecDesired {
-        freq: Some(SND_FREQ),
-        channels: Some(2), /* Mono */
-        samples: Some(1024), /* Buffer size */
+    let writer = match config.audio_out {
+        Some(ref path) => {
+            info!("writing {}Hz stereo to {}", freq, path.display());
+            Some(WavWriter::create(path, freq as u32, format.channels as u16)?)
+        },
+        None => {
+            info!("mixing {}Hz stereo without a device", freq);
+            None
+        }
     };
 
+    let (engine, producer) = create_engine(format, config);
+    let sink = Sink::new(engine, writer);
+    let music = Music::start(paths::data_file("music"), freq, producer, config)?;
 
-    for i in 0..MAX_SAMPLES {
-        let filename = format!("sfx/sfx_{:02}.wav", i);
-        let wave_load_state = AudioSpecWAV::load_wav(&filename);
+    Ok((Box::new(sink), music))
+}
 
-        if let Err(error) = wave_load_state {
-            println!("Error loading WAV: {}\n", error);
-            continue;
-        }
+/* the engine for a backend of the given format, and the end the music thread writes to */
+fn create_engine(format: Format, config: &GameConfig) -> (Engine, ring::Producer) {
+    let sfx_list = load_samples(format.freq, config);
 
-        let sfx_spec = wave_load_state.unwrap();
+    /* about 200ms, but never less than a few callbacks worth */
+    let ring_len = (format.freq as usize * 2 / 5).max(4 * format.samples as usize * format.channels as usize);
+    let (producer, consumer) = ring::ring(ring_len);
+    let volume = VOLUME.with(|volume_ref_cell| *volume_ref_cell.borrow());
 
-        let sfx_buffer = check_sample_format(&sfx_spec, &filename);
+    (Engine::new(format, sfx_list, consumer, volume), producer)
+}
 
-        if sfx_buffer.is_none() {
-            continue;
-        }
+fn load_samples(freq: i32, config: &GameConfig) -> Vec<WavStream> {
+    let sfx_dir = paths::data_file("sfx");
+    let pack = match config.sound_pack {
+        Some(ref pack_dir) => SoundPack::load(&sfx_dir, pack_dir).unwrap_or_else(|error| {
+            warn!("{}, using the original sounds", error);
+            SoundPack::original(&sfx_dir)
+        }),
+        None => SoundPack::original(&sfx_dir),
+    };
 
-        let sfx_buffer = sfx_buffer.unwrap();
+    let mut sfx_list = vec!();
+    let mut converted = 0;
+    let mut missing = 0;
+
+    for i in 0..MAX_SAMPLES as usize {
+        let sample = pack.sample(i);
+        let filename = sample.file.to_string_lossy();
+
+        /* missing samples stay in the list as silence, so the indices still match */
+        let sfx = match AudioSpecWAV::load_wav(&sample.file) {
+            Ok(sfx_spec) => {
+                if needs_conversion(&sfx_spec, freq) {
+                    info!("converting {}: {}Hz {} {} to {}Hz mono S16", filename, sfx_spec.freq,
+                        if sfx_spec.channels == 1 { "mono" } else { "stereo" },
+                        resample::format_name(sfx_spec.format), freq);
+                    converted += 1;
+                }
 
-        let sfx = match i {
-            19 => { // hyperspace
-                WavStream::new(sfx_buffer, SND_FREQ) // loop to about 0.5 sec in
-            },
+                let buffer = convert_sample(&sfx_spec, freq, sample.gain);
+                let loop_range = sample.loop_points(sfx_spec.freq).and_then(|points| {
+                    let range = loop_range(points, sfx_spec.freq, freq, buffer.len());
 
-            23 => { // and 23 (noise) loop
-                WavStream::new(sfx_buffer, 0) // loop to about 0.5 sec in
-            },
+                    if range.is_none() {
+                        warn!("{}: loop points are outside the sample, not looping", filename);
+                    }
 
-            _ => {
-                WavStream::new(sfx_buffer, -1)
+                    range
+                });
 
+                WavStream::new(buffer, loop_range)
+            },
+            Err(error) => {
+                warn!("unable to load {}: {}", filename, error);
+                missing += 1;
+                WavStream::new(vec!(), None)
             }
         };
 
         sfx_list.push(sfx);
     }
 
-    let audio_device_result = context.audio().open_playback(None, &desired_spec, |_spec| Callback::new(sfx_list));
+    info!("loaded {} of {} samples, {} converted", MAX_SAMPLES as usize - missing, MAX_SAMPLES, converted);
 
-    if let Err(error) = audio_device_result {
-        println!("Sound: {}", error);
-        return;
-    }
+    sfx_list
+}
 
-    let audio_device = audio_device_result.unwrap();
+/* loop points are in frames of the file, the range is in samples at the device rate */
+fn loop_range(points: LoopPoints, file_freq: i32, freq: i32, len: usize) -> Option<Range<usize>> {
+    let scale = |frame: u32| (frame as u64 * freq.max(1) as u64 / file_freq.max(1) as u64) as usize;
+    let end = points.end.map_or(len, |end| scale(end).min(len));
+    let start = scale(points.start);
 
-    /* And begin */
-    enable_audio(&audio_device, true);
+    Some(start..end).filter(|range| range.start < range.end)
+}
 
-    AUDIO_DEVICE.with(|audio_device_ref_cell| {
-        *audio_device_ref_cell.borrow_mut() = Some(audio_device);
-    });
+fn needs_conversion(spec: &AudioSpecWAV, freq: i32) -> bool {
+    spec.freq != freq || spec.channels != 1 || spec.format != AudioFormat::S16LSB
 }
 
 /*
-* Loaded samples must be SND_FREQ, 16-bit signed. Reject
-* other frequencies but convert 8-bit unsigned.
+* Samples can be of any rate and format, they are all converted to the
+* rate of the device, 16-bit signed mono when they are loaded.
 */
-fn check_sample_format(spec: &AudioSpecWAV, filename: &str) -> Option<Vec<u8>> {
+fn convert_sample(spec: &AudioSpecWAV, freq: i32, gain: f32) -> Vec<i16> {
+    let mut samples = resample::to_mono(spec.format, spec.channels, spec.buffer());
 
-    if spec.freq != SND_FREQ {
-        println!("Sample {} is the wrong sample rate (wanted {}Hz). Ignoring.\n", filename, SND_FREQ);
-        return None;
+    if gain != 1.0 {
+        samples.iter_mut().for_each(|sample| *sample *= gain);
     }
 
-    match spec.format {
-        AudioFormat::U8 => {
-            let old_buffer = spec.buffer();
-            let new_buffer: Vec<u8> = old_buffer.iter()
-                .map(|byte| {
-                    let large_byte = (((byte ^ 128) as i8) as i16) << 8;
+    let samples = resample::resample(&samples, spec.freq as u32, freq as u32);
 
-                    vec!((large_byte & 0x00FF) as u8, ((large_byte as u16 & 0xFF00) >> 8) as u8)
-                })
-                .collect::<Vec<Vec<u8>>>()
-                .concat();
+    resample::to_i16(&samples)
+}
 
-            Some(new_buffer)
-        },
+pub fn uninit() {
+    AUDIO_OUTPUT.with(|audio_output_ref_cell| {
+        /* closes the device and stops the callback thread, or finishes the wav file */
+        audio_output_ref_cell.borrow_mut().take();
+    });
 
-        AudioFormat::S16LSB => {
-            Some(spec.buffer().to_owned())
-        },
+    MUSIC.with(|music_ref_cell| {
+        /* waits for the music thread to finish */
+        music_ref_cell.borrow_mut().take();
+    });
 
-        _ => {
-            println!("Sample {} is not 16-bit-signed or 8-bit unsigned. Ignoring.", filename);
-            None
-        }
-    }
+    HOTPLUG.with(|hotplug_ref_cell| {
+        hotplug_ref_cell.borrow_mut().take();
+    });
 }
 
-fn enable_audio(audio_device: &AudioDevice<Callback>, enabled: bool) {
-    if !enabled {
-        return audio_device.pause();
-    }
+pub fn set_enabled(enabled: bool) {
+    HOTPLUG.with(|hotplug_ref_cell| {
+        if let Some(ref mut hotplug) = *hotplug_ref_cell.borrow_mut() {
+            hotplug.enabled = enabled;
+        }
+    });
 
-    audio_device.resume();
+    with_static_ref_option!([AUDIO_OUTPUT => output] {
+        output.set_paused(!enabled);
+    } or {});
 }
 
-fn play_music_track(playback_context: &mut Callback, track: usize) {
-	let file_path = format!("music/{:02}.ogg", track);
-	let file = File::open(&file_path).expect(&format!("Can't open file {}", &file_path));
-    let reader = OggStreamReader::new(file).expect("vorbis open");
+/* moves the mixer to another device if ours is gone */
+pub fn device_removed() {
+    with_static_ref_option!([HOTPLUG => hotplug] {
+        /* it might have been the device that failed, give it another chance when it is back */
+        hotplug.failed = None;
+        hotplug.announced = hotplug.startup_devices;
 
-    playback_context.music_file = Some(reader);
-}
+        AUDIO_OUTPUT.with(|audio_output_ref_cell| {
+            let output = &mut *audio_output_ref_cell.borrow_mut();
 
-fn rand_tracknum(playback_context: &Callback) -> usize {
-    if playback_context.enabled_tracks == 0 {
-        return 999;
-    }
+            if !matches!(output, Some(lost) if lost.is_lost()) {
+                return;
+            }
 
-    let mut rng = rand::thread_rng();
-    let mut track;
+            warn!("lost {}", hotplug.current.as_deref().unwrap_or("the default audio device"));
 
-    loop {
-        let rand = rng.gen::<usize>();
-    	track = rand % 8;
+            if let Some(lost) = output.take() {
+                *output = Some(hotplug.reopen(lost.into_engine()));
+            }
+        });
+    } or {});
+}
+
+/* goes back to the configured device when it returns, or leaves the sink for any device */
+pub fn device_added(index: u32) {
+    with_static_ref_option!([HOTPLUG => hotplug] {
+        if hotplug.announced < hotplug.startup_devices && index < hotplug.startup_devices {
+            hotplug.announced += 1;
+            return;
+        }
 
-        println!("chose track: {} {}", rand, track);
+        /* a device that wasn't there at startup, the announcements are over */
+        hotplug.announced = hotplug.startup_devices;
 
-        if (playback_context.enabled_tracks & (1<<track)) != 0 {
-            break;
+        let name = hotplug.audio.audio_playback_device_name(index).ok();
+
+        if name.is_some() && name == hotplug.failed {
+            debug!("{} didn't open before, leaving it alone", name.as_deref().unwrap_or_default());
+            return;
         }
-    }
 
-    track
+        AUDIO_OUTPUT.with(|audio_output_ref_cell| {
+            let output = &mut *audio_output_ref_cell.borrow_mut();
+            let wanted_returned = hotplug.wanted.is_some() && name == hotplug.wanted && hotplug.current != hotplug.wanted;
+
+            if !wanted_returned && matches!(output, Some(current) if current.has_device()) {
+                return;
+            }
+
+            info!("{} plugged in", name.as_deref().unwrap_or("audio device"));
+
+            if let Some(previous) = output.take() {
+                *output = Some(hotplug.reopen(previous.into_engine()));
+            }
+        });
+    } or {});
 }
 
-fn stop_music_track(playback_context: &mut Callback) {
-    playback_context.music_file = None;
+/* runs a sink for a span of emulated time, a device runs on its own */
+pub fn advance(elapsed: Duration) {
+    with_static_ref_option!([AUDIO_OUTPUT => output] {
+        output.advance(elapsed);
+    } or {});
 }
 
-pub fn play_sfx(sfx_index: usize, channel: usize) {
-    with_audio_context!([AUDIO_DEVICE => audio_context] {
-        println!("playing sfx {:02}", sfx_index);
+/* steps a volume level up or down, returns the new level in percent */
+pub fn change_volume(control: VolumeControl, step: i32) -> u8 {
+    let (volume, level) = VOLUME.with(|volume_ref_cell| {
+        let volume = &mut *volume_ref_cell.borrow_mut();
+        let level = volume.level_mut(control);
+
+        *level = (*level as i32 + step).clamp(0, MAX_VOLUME as i32) as u8;
 
-        audio_context.set_wav_channel(channel, WavChannel::from_stream(&audio_context.sfx_list, &audio_context.sfx_list[sfx_index]));
+        let level = *level;
+        (*volume, level)
     });
+
+    with_audio_context!([AUDIO_OUTPUT => audio_context] {
+        audio_context.set_volume(volume);
+    });
+
+    config::update(|config| {
+        config.master_volume = volume.master;
+        config.music_volume = volume.music;
+        config.sfx_volume = volume.sfx;
+    });
+
+    debug!("{:?} volume set to {}%", control, level);
+    level
 }
 
-pub fn is_music_playing() -> bool {
-    let mut is_playing = false;
+pub fn play_sfx(sfx_index: usize, channel: usize) {
+    start_sfx(sfx_index, channel, Placement::default());
+}
+
+/* pan goes from -1 (left) to 1 (right), distance is in the game's units up to 63 */
+pub fn play_sfx_at(sfx_index: usize, pan: f32, distance: u16) {
+    let gain = 1.0 - distance.min(SFX_MAX_DISTANCE) as f32 / SFX_MAX_DISTANCE as f32;
+
+    with_audio_context!([AUDIO_OUTPUT => audio_context] {
+        audio_context.play_positioned(sfx_index, Placement { pan, gain });
+    });
+}
 
-    with_audio_context!([AUDIO_DEVICE => audio_context] {
-        is_playing = audio_context.music_file.is_some();
+fn start_sfx(sfx_index: usize, channel: usize, placement: Placement) {
+    with_audio_context!([AUDIO_OUTPUT => audio_context] {
+        audio_context.play_sfx(sfx_index, channel, placement);
     });
+}
 
-    is_playing
+pub fn is_music_playing() -> bool {
+    MUSIC.with(|music_ref_cell| {
+        matches!(*music_ref_cell.borrow(), Some(ref music) if music.is_playing())
+    })
 }
 
 /* music_mode:
- * -2 = play random track once
+ * -3 = play random hyperspace track once
+ * -2 = play random battle track once
  * -1 = play random tracks continuously
  * 0+ = play specific track once
  *
  * enabled_tracks: is a mask of enabled tracks
  */
 pub fn play_music(music_mode: isize, enabled_tracks: usize) {
-    with_audio_context!([AUDIO_DEVICE => audio_context] {
-        let mut enabled_tracks = enabled_tracks;
-
-        let track = match music_mode {
-            -2 | -1 => {
-                // hyperspace and battle music
-                // don't play blue danube or reward music
-                enabled_tracks &= !0x40;
-    			enabled_tracks &= !0x80;
-
-                rand_tracknum(audio_context)
-            },
-            _ => music_mode as usize
-        };
-
-        audio_context.music_mode = music_mode;
-        audio_context.enabled_tracks = enabled_tracks;
-
-        play_music_track(audio_context, track);
+    with_static_ref_option!([MUSIC => music] {
+        music.play(music_mode, enabled_tracks);
+    } or {
+        debug!("no audio device initialized");
     });
 }
 
 pub fn stop_music() {
-    with_audio_context!([AUDIO_DEVICE => audio_context] {
-        stop_music_track(audio_context);
-    });
+    with_static_ref_option!([MUSIC => music] {
+        music.stop();
+    } or {});
+}
+
+/* logs what went wrong on the music thread, called from the main loop */
+pub fn report_errors() {
+    with_static_ref_option!([MUSIC => music] {
+        for error in music.errors() {
+            error!("{}", error);
+        }
+    } or {});
 }
@@ -0,0 +1,122 @@
+/*
+ * Where the engine's buffers go. SDL pulls them from its own thread, a
+ * sink is pushed once per VBL. Either way the engine can be taken back out
+ * and moved to another backend with its voices and music intact.
+ */
+
+use std::ops::{ Deref, DerefMut };
+use std::time::Duration;
+
+use sdl2::AudioSubsystem;
+use sdl2::audio::{
+    AudioCallback, AudioDevice, AudioDeviceLockGuard, AudioSpec, AudioSpecDesired, AudioStatus
+};
+
+use crate::error::{ Error, Result };
+
+use super::engine::{ Engine, Format };
+
+/* the engine, with the backend kept from mixing while this is held */
+pub type EngineLock<'a> = Box<dyn DerefMut<Target = Engine> + 'a>;
+
+pub trait Backend {
+    fn lock(&mut self) -> EngineLock<'_>;
+
+    fn set_paused(&mut self, paused: bool);
+
+    /* mixes a span of emulated time, for backends without a clock of their own */
+    fn advance(&mut self, _elapsed: Duration) {}
+
+    /* false for sinks, they are only a stand-in for a device */
+    fn has_device(&self) -> bool;
+
+    /* the device has gone away and won't ask for anything anymore */
+    fn is_lost(&self) -> bool {
+        false
+    }
+
+    fn into_engine(self: Box<Self>) -> Engine;
+}
+
+struct SdlCallback(Engine);
+
+impl AudioCallback for SdlCallback {
+    type Channel = i16; /* 16 Bit signed stereo */
+
+    fn callback(&mut self, dest_buffer: &mut [Self::Channel]) {
+        self.0.mix(dest_buffer);
+    }
+}
+
+struct SdlLock<'a>(AudioDeviceLockGuard<'a, SdlCallback>);
+
+impl Deref for SdlLock<'_> {
+    type Target = Engine;
+
+    fn deref(&self) -> &Engine {
+        &self.0.0
+    }
+}
+
+impl DerefMut for SdlLock<'_> {
+    fn deref_mut(&mut self) -> &mut Engine {
+        &mut self.0.0
+    }
+}
+
+pub struct SdlBackend {
+    device: AudioDevice<SdlCallback>,
+}
+
+impl SdlBackend {
+    /*
+     * The named device if there is one, SDL's default otherwise, and the
+     * name of what was opened. get_engine is only called once a device has
+     * been opened, with the format SDL gave us.
+     */
+    pub fn open<F>(audio: &AudioSubsystem, name: Option<&str>, desired_spec: &AudioSpecDesired, mut get_engine: F)
+        -> Result<(Self, Option<String>)>
+        where F: FnMut(Format) -> Engine
+    {
+        let mut get_callback = |spec: AudioSpec| {
+            SdlCallback(get_engine(Format { freq: spec.freq, channels: spec.channels, samples: spec.samples }))
+        };
+
+        if let Some(name) = name {
+            match audio.open_playback(name, desired_spec, &mut get_callback) {
+                Ok(device) => return Ok((Self { device }, Some(name.to_string()))),
+                Err(error) => warn!("unable to open {}: {}, trying the default device", name, error),
+            }
+        }
+
+        audio.open_playback(None, desired_spec, get_callback)
+            .map(|device| (Self { device }, None))
+            .map_err(Error::Audio)
+    }
+}
+
+impl Backend for SdlBackend {
+    fn lock(&mut self) -> EngineLock<'_> {
+        Box::new(SdlLock(self.device.lock()))
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        if paused {
+            return self.device.pause();
+        }
+
+        self.device.resume();
+    }
+
+    fn has_device(&self) -> bool {
+        true
+    }
+
+    fn is_lost(&self) -> bool {
+        self.device.status() == AudioStatus::Stopped
+    }
+
+    fn into_engine(self: Box<Self>) -> Engine {
+        self.device.close_and_get_callback().0
+    }
+}
@@ -0,0 +1,339 @@
+/*
+ * The sfx voices and the mix, without anything of SDL. A backend owns the
+ * engine and asks it for buffers, the game starts sounds through it while
+ * the backend holds it still.
+ */
+
+use std::ops::Range;
+
+use super::{ gain, Volume };
+use super::mixer::{ Mixer, Placement };
+use super::ring;
+
+/* the channels the game plays on itself */
+pub static GAME_CHANNELS: usize = 4;
+/* sounds of objects in the 3d view take turns on these */
+static POSITIONED_VOICES: usize = 4;
+pub static MAX_CHANNELS: usize = GAME_CHANNELS + POSITIONED_VOICES;
+static MUSIC_CHANNEL: usize = MAX_CHANNELS;
+
+/* what the engine mixes for, another backend has to take exactly this */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Format {
+    pub freq: i32,
+    pub channels: u8,
+    /* frames per buffer */
+    pub samples: u16,
+}
+
+/* mono samples at the device rate, whatever the wav file was */
+#[derive(Clone, PartialEq)]
+pub struct WavStream {
+    buffer: Vec<i16>,
+    loop_range: Option<Range<usize>>,
+}
+
+impl WavStream {
+    pub fn new(buffer: Vec<i16>, loop_range: Option<Range<usize>>) -> Self {
+        Self {
+            buffer,
+            loop_range,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct WavChannel {
+    buffer_pos: usize,
+    loop_range: Option<Range<usize>>,
+    buffer_ref: usize,
+    placement: Placement,
+    /* counts up with every sound started, the lowest has played longest */
+    started: u64,
+}
+
+impl WavChannel {
+    fn from_stream(index: usize, stream: &WavStream, placement: Placement, started: u64) -> Self {
+        Self {
+            buffer_pos: 0,
+            loop_range: stream.loop_range.clone(),
+            buffer_ref: index,
+            placement,
+            started,
+        }
+    }
+}
+
+pub struct Engine {
+    format: Format,
+    wav_channels: Vec<Option<WavChannel>>,
+    sfx_list: Vec<WavStream>,
+    mixer: Mixer,
+    /* decoded stereo music at the device rate, filled by the music thread */
+    music: ring::Consumer,
+    music_samples: Vec<i16>,
+    started: u64,
+    /* where the search for a free positioned voice starts */
+    next_voice: usize,
+}
+
+impl Engine {
+    pub fn new(format: Format, sfx_list: Vec<WavStream>, music: ring::Consumer, volume: Volume) -> Self {
+        let wav_channels = vec![None; MAX_CHANNELS];
+
+        /* one mixer channel per sfx channel, plus the music */
+        let mut channel_gains = vec![gain(volume.sfx); MAX_CHANNELS];
+        channel_gains.push(gain(volume.music));
+
+        Self {
+            format,
+            wav_channels,
+            sfx_list,
+            mixer: Mixer::new(channel_gains, gain(volume.master)),
+            music,
+            /* sized for the buffers the backend asks for, so mixing doesn't allocate */
+            music_samples: vec![0; format.samples as usize * format.channels as usize],
+            started: 0,
+            next_voice: 0,
+        }
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    pub fn set_volume(&mut self, volume: Volume) {
+        for channel in 0..MAX_CHANNELS {
+            self.mixer.set_channel_gain(channel, gain(volume.sfx));
+        }
+
+        self.mixer.set_channel_gain(MUSIC_CHANNEL, gain(volume.music));
+        self.mixer.set_master_gain(gain(volume.master));
+    }
+
+    /* whatever played on the channel is cut off, like on the ST */
+    pub fn play_sfx(&mut self, sfx_index: usize, channel: usize, placement: Placement) {
+        if channel >= GAME_CHANNELS {
+            warn!("invalid sfx channel {}", channel);
+            return;
+        }
+
+        self.start(sfx_index, channel, placement);
+    }
+
+    /*
+     * On a free one of the positioned voices, taken in turn. When all of
+     * them are busy the sound that has played longest makes way.
+     */
+    pub fn play_positioned(&mut self, sfx_index: usize, placement: Placement) {
+        let pool = GAME_CHANNELS..MAX_CHANNELS;
+        let voice = (0..POSITIONED_VOICES)
+            .map(|i| GAME_CHANNELS + (self.next_voice + i) % POSITIONED_VOICES)
+            .find(|&voice| self.wav_channels[voice].is_none())
+            .or_else(|| pool.min_by_key(|&voice| self.wav_channels[voice].as_ref().map_or(0, |channel| channel.started)))
+            .unwrap_or(GAME_CHANNELS);
+
+        self.next_voice = (voice - GAME_CHANNELS + 1) % POSITIONED_VOICES;
+        self.start(sfx_index, voice, placement);
+    }
+
+    fn start(&mut self, sfx_index: usize, channel: usize, placement: Placement) {
+        let stream = match self.sfx_list.get(sfx_index) {
+            Some(stream) if !stream.buffer.is_empty() => stream,
+            _ => {
+                debug!("sfx {:02} is not loaded", sfx_index);
+                return;
+            }
+        };
+
+        debug!("playing sfx {:02} on {}, pan {:.2}, gain {:.2}", sfx_index, channel, placement.pan, placement.gain);
+
+        self.started += 1;
+        self.wav_channels[channel] = Some(WavChannel::from_stream(sfx_index, stream, placement, self.started));
+    }
+
+    /* fills an interleaved stereo buffer */
+    pub fn mix(&mut self, dest_buffer: &mut [i16]) {
+        let len = dest_buffer.len();
+
+        self.mixer.begin(len);
+        self.mix_music(len);
+        self.mix_sfx(len);
+        self.mixer.finish(dest_buffer);
+    }
+
+    fn mix_music(&mut self, len: usize) {
+        if self.music_samples.len() < len {
+            self.music_samples.resize(len, 0);
+        }
+
+        /* on an underrun the music is just quiet for a moment */
+        let music_len = self.music.pop(&mut self.music_samples[..len]);
+
+        self.mixer.add(MUSIC_CHANNEL, 0, &self.music_samples[..music_len]);
+    }
+
+    fn mix_sfx(&mut self, len: usize) {
+        for j in 0..MAX_CHANNELS {
+            let channel = match self.wav_channels[j] {
+                Some(ref mut channel) => channel,
+                None => continue,
+            };
+
+            let buffer = &self.sfx_list[channel.buffer_ref].buffer;
+            let end = channel.loop_range.as_ref().map_or(buffer.len(), |loop_range| loop_range.end);
+            let mut finished = false;
+
+            for i in (0..len).step_by(2) {
+                self.mixer.add_mono(j, i, buffer[channel.buffer_pos], &channel.placement);
+                channel.buffer_pos += 1;
+
+                if channel.buffer_pos < end {
+                    continue;
+                }
+
+                /* end of sample. either loop or terminate */
+                if let Some(ref loop_range) = channel.loop_range {
+                    channel.buffer_pos = loop_range.start;
+                    continue;
+                }
+
+                finished = true;
+                break;
+            }
+
+            if finished {
+                self.wav_channels[j] = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static FORMAT: Format = Format { freq: 22050, channels: 2, samples: 16 };
+
+    fn engine(sfx_list: Vec<WavStream>) -> (Engine, ring::Producer) {
+        let (producer, consumer) = ring::ring(64);
+
+        (Engine::new(FORMAT, sfx_list, consumer, Volume::default()), producer)
+    }
+
+    /* the left side of frames stereo frames */
+    fn mix_left(engine: &mut Engine, frames: usize) -> Vec<i16> {
+        let mut buffer = vec![0; frames * 2];
+
+        engine.mix(&mut buffer);
+        buffer.iter().step_by(2).cloned().collect()
+    }
+
+    #[test]
+    fn looping_voices_wrap_to_the_loop_start() {
+        let (mut engine, _music) = engine(vec![WavStream::new(vec![1, 2, 3, 4], Some(1..3))]);
+
+        engine.play_sfx(0, 1, Placement::default());
+
+        assert_eq!(mix_left(&mut engine, 7), vec![1, 2, 3, 2, 3, 2, 3]);
+        assert!(engine.wav_channels[1].is_some());
+    }
+
+    #[test]
+    fn voices_without_a_loop_end_free_their_channel() {
+        let (mut engine, _music) = engine(vec![WavStream::new(vec![5, 6], None)]);
+
+        engine.play_sfx(0, 0, Placement::default());
+
+        assert_eq!(mix_left(&mut engine, 4), vec![5, 6, 0, 0]);
+        assert!(engine.wav_channels[0].is_none());
+        assert_eq!(mix_left(&mut engine, 2), vec![0, 0]);
+    }
+
+    #[test]
+    fn a_new_sfx_steals_its_channel() {
+        let (mut engine, _music) = engine(vec![
+            WavStream::new(vec![10; 8], None),
+            WavStream::new(vec![1, 2, 3], None),
+        ]);
+
+        engine.play_sfx(0, 2, Placement::default());
+        assert_eq!(mix_left(&mut engine, 2), vec![10, 10]);
+
+        engine.play_sfx(1, 2, Placement::default());
+        assert_eq!(mix_left(&mut engine, 4), vec![1, 2, 3, 0]);
+
+        /* other channels are left alone */
+        engine.play_sfx(0, 2, Placement::default());
+        engine.play_sfx(1, 3, Placement::default());
+        assert_eq!(mix_left(&mut engine, 4), vec![11, 12, 13, 10]);
+    }
+
+    #[test]
+    fn invalid_channels_and_unloaded_sfx_are_ignored() {
+        let (mut engine, _music) = engine(vec![WavStream::new(vec![1], None), WavStream::new(vec!(), None)]);
+
+        engine.play_sfx(0, GAME_CHANNELS, Placement::default());
+        engine.play_sfx(1, 0, Placement::default());
+        engine.play_sfx(2, 0, Placement::default());
+
+        assert!(engine.wav_channels.iter().all(|channel| channel.is_none()));
+    }
+
+    #[test]
+    fn positioned_sounds_play_at_the_same_time() {
+        let (mut engine, _music) = engine(vec![WavStream::new(vec![100; 4], None), WavStream::new(vec![10; 4], None)]);
+        let left = Placement { pan: -1.0, gain: 1.0 };
+        let right = Placement { pan: 1.0, gain: 1.0 };
+        let mut buffer = vec![0; 2];
+
+        engine.play_positioned(0, left);
+        engine.play_positioned(1, right);
+        engine.mix(&mut buffer);
+
+        assert_eq!(buffer, vec![100, 10]);
+    }
+
+    #[test]
+    fn a_full_pool_gives_up_the_oldest_positioned_sound() {
+        let (mut engine, _music) = engine(vec![WavStream::new(vec![1; 8], None), WavStream::new(vec![1000; 8], None)]);
+
+        for _ in 0..POSITIONED_VOICES {
+            engine.play_positioned(0, Placement::default());
+        }
+
+        engine.play_positioned(1, Placement::default());
+
+        let voices: Vec<usize> = engine.wav_channels[GAME_CHANNELS..].iter()
+            .map(|channel| channel.as_ref().unwrap().buffer_ref)
+            .collect();
+
+        assert_eq!(voices.iter().filter(|&&sfx| sfx == 1).count(), 1);
+        assert_eq!(voices[0], 1);
+        assert_eq!(mix_left(&mut engine, 1), vec![1000 + (POSITIONED_VOICES as i16 - 1)]);
+    }
+
+    #[test]
+    fn music_comes_from_the_ring_and_is_silent_on_underrun() {
+        let (mut engine, music) = engine(vec!());
+        let mut buffer = vec![0; 6];
+
+        music.push(&[100, -100, 200, -200]);
+        engine.mix(&mut buffer);
+
+        assert_eq!(buffer, vec![100, -100, 200, -200, 0, 0]);
+    }
+
+    #[test]
+    fn volume_scales_sfx_and_music_separately() {
+        let (mut engine, music) = engine(vec![WavStream::new(vec![1000; 4], None)]);
+        let mut buffer = vec![0; 2];
+
+        engine.set_volume(Volume { master: 50, music: 100, sfx: 0 });
+        engine.play_sfx(0, 0, Placement::default());
+        music.push(&[1000, 2000]);
+        engine.mix(&mut buffer);
+
+        assert_eq!(buffer, vec![500, 1000]);
+    }
+}
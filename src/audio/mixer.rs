@@ -0,0 +1,219 @@
+/*
+ * Sums the sfx channels and the music in f32, so nothing can wrap while
+ * mixing. The sum is run through a soft limiter before it goes back to
+ * i16: quiet passages are left alone, loud ones are squashed smoothly
+ * towards full scale instead of being clipped hard.
+ */
+
+static FULL_SCALE: f32 = 32767.0;
+
+/* everything below this fraction of full scale passes unchanged */
+static LIMITER_THRESHOLD: f32 = 0.8;
+
+/* where a mono voice sits in the stereo field and how loud it is */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Placement {
+    /* -1 is hard left, 1 hard right */
+    pub pan: f32,
+    pub gain: f32,
+}
+
+impl Default for Placement {
+    fn default() -> Self {
+        Self { pan: 0.0, gain: 1.0 }
+    }
+}
+
+impl Placement {
+    /*
+     * A balance control: the far side is turned down, the near one stays.
+     * Centred voices come out exactly as loud as before there was panning.
+     */
+    fn side_gains(&self) -> [f32; 2] {
+        let pan = self.pan.clamp(-1.0, 1.0);
+        let gain = self.gain.max(0.0);
+
+        [gain * (1.0 - pan).min(1.0), gain * (1.0 + pan).min(1.0)]
+    }
+}
+
+pub struct Mixer {
+    master_gain: f32,
+    channel_gains: Vec<f32>,
+    buffer: Vec<f32>,
+}
+
+impl Mixer {
+    /* a channel without a gain is muted */
+    pub fn new(channel_gains: Vec<f32>, master_gain: f32) -> Self {
+        Self {
+            master_gain: master_gain.max(0.0),
+            channel_gains: channel_gains.into_iter().map(|gain| gain.max(0.0)).collect(),
+            buffer: vec!(),
+        }
+    }
+
+    pub fn set_master_gain(&mut self, gain: f32) {
+        self.master_gain = gain.max(0.0);
+    }
+
+    pub fn set_channel_gain(&mut self, channel: usize, gain: f32) {
+        if let Some(channel_gain) = self.channel_gains.get_mut(channel) {
+            *channel_gain = gain.max(0.0);
+        }
+    }
+
+    /* starts a new buffer of len interleaved samples, all silent */
+    pub fn begin(&mut self, len: usize) {
+        self.buffer.clear();
+        self.buffer.resize(len, 0.0);
+    }
+
+    /* adds interleaved samples of a channel, starting at offset */
+    pub fn add(&mut self, channel: usize, offset: usize, samples: &[i16]) {
+        let gain = self.channel_gains.get(channel).cloned().unwrap_or(0.0);
+
+        for (mixed, sample) in self.buffer.iter_mut().skip(offset).zip(samples) {
+            *mixed += *sample as f32 * gain;
+        }
+    }
+
+    /* adds a mono sample to the stereo frame at offset, placed in the field */
+    pub fn add_mono(&mut self, channel: usize, offset: usize, sample: i16, placement: &Placement) {
+        let gain = self.channel_gains.get(channel).cloned().unwrap_or(0.0);
+
+        for (mixed, side_gain) in self.buffer.iter_mut().skip(offset).take(2).zip(&placement.side_gains()) {
+            *mixed += sample as f32 * gain * side_gain;
+        }
+    }
+
+    pub fn finish(&self, dest_buffer: &mut [i16]) {
+        for (dest, mixed) in dest_buffer.iter_mut().zip(&self.buffer) {
+            let limited = limit(mixed * self.master_gain / FULL_SCALE);
+
+            *dest = (limited * FULL_SCALE).round() as i16;
+        }
+    }
+}
+
+/* maps any value into [-1, 1], linear up to the threshold */
+fn limit(value: f32) -> f32 {
+    let magnitude = value.abs();
+
+    if magnitude <= LIMITER_THRESHOLD {
+        return value;
+    }
+
+    let headroom = 1.0 - LIMITER_THRESHOLD;
+    let limited = LIMITER_THRESHOLD + headroom * ((magnitude - LIMITER_THRESHOLD) / headroom).tanh();
+
+    limited.min(1.0).copysign(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mix(mixer: &mut Mixer, channels: &[&[i16]]) -> Vec<i16> {
+        let len = channels.iter().map(|samples| samples.len()).max().unwrap_or(0);
+        let mut output = vec![0; len];
+
+        mixer.begin(len);
+
+        for (channel, samples) in channels.iter().enumerate() {
+            mixer.add(channel, 0, samples);
+        }
+
+        mixer.finish(&mut output);
+        output
+    }
+
+    #[test]
+    fn quiet_signals_pass_unchanged() {
+        let mut mixer = Mixer::new(vec![1.0; 2], 1.0);
+        let output = mix(&mut mixer, &[&[1000, -2000, 12000], &[500, 500, -12000]]);
+
+        assert_eq!(output, vec![1500, -1500, 0]);
+    }
+
+    #[test]
+    fn full_scale_channels_never_wrap() {
+        let mut mixer = Mixer::new(vec![1.0; 5], 1.0);
+        let loud: Vec<i16> = vec![i16::MAX, i16::MIN, i16::MAX, i16::MIN];
+        let channels: Vec<&[i16]> = (0..5).map(|_| &loud[..]).collect();
+
+        let output = mix(&mut mixer, &channels);
+
+        for (sample, expected) in output.iter().zip(&loud) {
+            /* the sum of 5 loud samples must stay loud and keep its sign */
+            assert!(sample.signum() == expected.signum());
+            assert!(sample.abs() > 30000);
+            assert!(*sample >= -32767);
+        }
+    }
+
+    #[test]
+    fn gains_are_applied_and_limited() {
+        let mut mixer = Mixer::new(vec![0.5, 0.0], 1.0);
+        assert_eq!(mix(&mut mixer, &[&[10000], &[10000], &[10000]]), vec![5000]);
+
+        let mut mixer = Mixer::new(vec![1.0, 1.0], 100.0);
+        let output = mix(&mut mixer, &[&[10000, -10000], &[0, 0]]);
+        assert!(output[0] > 30000);
+        assert!(output[1] < -30000 && output[1] >= -32767);
+    }
+
+    #[test]
+    fn limiter_is_monotonic_and_bounded() {
+        let mut previous = limit(0.0);
+
+        for step in 1..10000 {
+            let value = step as f32 / 100.0;
+            let limited = limit(value);
+
+            assert!(limited >= previous);
+            assert!(limited <= 1.0);
+            assert_eq!(limit(-value), -limited);
+            previous = limited;
+        }
+    }
+
+    #[test]
+    fn gains_can_be_changed() {
+        let mut mixer = Mixer::new(vec![1.0, 1.0], 1.0);
+
+        mixer.set_channel_gain(1, 0.0);
+        mixer.set_channel_gain(2, 1.0);
+        assert_eq!(mix(&mut mixer, &[&[1000], &[1000], &[1000]]), vec![1000]);
+
+        mixer.set_master_gain(0.5);
+        mixer.set_channel_gain(1, 1.0);
+        assert_eq!(mix(&mut mixer, &[&[1000], &[1000]]), vec![1000]);
+    }
+
+    #[test]
+    fn mono_samples_go_to_both_sides() {
+        let mut mixer = Mixer::new(vec![1.0], 1.0);
+        let mut output = vec![0; 4];
+
+        mixer.begin(4);
+        mixer.add_mono(0, 2, 1234, &Placement::default());
+        mixer.finish(&mut output);
+
+        assert_eq!(output, vec![0, 0, 1234, 1234]);
+    }
+
+    #[test]
+    fn placed_voices_are_panned_and_attenuated() {
+        let mut mixer = Mixer::new(vec![1.0], 1.0);
+        let mut output = vec![0; 6];
+
+        mixer.begin(6);
+        mixer.add_mono(0, 0, 1000, &Placement { pan: -1.0, gain: 1.0 });
+        mixer.add_mono(0, 2, 1000, &Placement { pan: 0.5, gain: 0.5 });
+        mixer.add_mono(0, 4, 1000, &Placement { pan: 3.0, gain: 1.0 });
+        mixer.finish(&mut output);
+
+        assert_eq!(output, vec![1000, 0, 250, 500, 0, 1000]);
+    }
+}
@@ -0,0 +1,687 @@
+/*
+ * Music is opened, decoded and resampled on its own thread, which keeps the
+ * ring buffer topped up. The audio callback only copies samples out of the
+ * ring, so slow disks or a broken file can't make it miss a deadline.
+ * Errors are sent back and reported from the main thread.
+ *
+ * Fades are mixed in here as well, so they start after what is already in
+ * the ring. That is why the ring is kept short.
+ */
+
+use std::collections::{ HashMap, VecDeque };
+use std::convert::TryFrom;
+use std::fs::File;
+use std::path::{ Path, PathBuf };
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicBool, AtomicUsize, Ordering };
+use std::sync::mpsc::{ self, Receiver, RecvTimeoutError, Sender, TryRecvError };
+use std::thread::{ self, JoinHandle };
+use std::time::Duration;
+
+use lewton::inside_ogg::OggStreamReader;
+
+use crate::GameConfig;
+use crate::error::{ Error, Result };
+
+use super::playlist::Playlist;
+use super::resample::StreamResampler;
+use super::ring::Producer;
+use super::soundtrack::{ MusicContext, Soundtrack };
+
+/* how long to wait for room in a full ring before trying again */
+static FULL_WAIT: Duration = Duration::from_millis(5);
+
+enum Command {
+    Play { music_mode: isize, enabled_tracks: usize },
+    Stop,
+    Quit,
+}
+
+/*
+ * What the game asks with is_music_playing. Commands that haven't been
+ * handled yet count as done, so the answer doesn't flicker while the
+ * thread gets to them.
+ */
+#[derive(Default)]
+struct Status {
+    playing: AtomicBool,
+    requested: AtomicBool,
+    pending: AtomicUsize,
+}
+
+/* a gain moving linearly towards a target, one step per stereo frame */
+#[derive(Clone, Copy)]
+struct Fade {
+    gain: f32,
+    target: f32,
+    step: f32,
+}
+
+impl Fade {
+    fn constant(gain: f32) -> Self {
+        Self { gain, target: gain, step: 0.0 }
+    }
+
+    /* from the current gain to target, over frames */
+    fn towards(self, target: f32, frames: usize) -> Self {
+        let step = (target - self.gain).abs() / frames.max(1) as f32;
+
+        Self { gain: self.gain, target, step }
+    }
+
+    fn next(&mut self) -> f32 {
+        let gain = self.gain;
+
+        self.gain = if self.gain < self.target {
+            (self.gain + self.step).min(self.target)
+        } else {
+            (self.gain - self.step).max(self.target)
+        };
+
+        gain
+    }
+
+    fn is_silent(&self) -> bool {
+        self.gain <= 0.0 && self.target <= 0.0
+    }
+}
+
+/* where the decoded packets of a track come from */
+trait Packets: Send {
+    /* interleaved samples, None at the end of the track */
+    fn next_packet(&mut self) -> Result<Option<Vec<i16>>>;
+}
+
+impl Packets for OggStreamReader<File> {
+    fn next_packet(&mut self) -> Result<Option<Vec<i16>>> {
+        Ok(self.read_dec_packet_itl()?)
+    }
+}
+
+/* a track and what it takes to get it to the device rate */
+struct MusicStream {
+    packets: Box<dyn Packets>,
+    channels: usize,
+    resampler: StreamResampler,
+    /* resampled stereo samples that haven't been mixed yet */
+    buffer: VecDeque<i16>,
+    fade: Fade,
+}
+
+impl MusicStream {
+    fn new(packets: Box<dyn Packets>, channels: usize, file_freq: u32, freq: i32) -> Self {
+        Self {
+            packets,
+            channels,
+            resampler: StreamResampler::new(file_freq, freq as u32),
+            buffer: VecDeque::new(),
+            fade: Fade::constant(1.0),
+        }
+    }
+
+    /* decodes the next packet into the buffer, false at the end of the track */
+    fn decode(&mut self) -> Result<bool> {
+        match self.packets.next_packet()? {
+            Some(packet) => {
+                self.resampler.process(&packet, self.channels, &mut self.buffer);
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    /* adds the faded samples to mixed and the gain of each frame to gains */
+    fn mix_into(&mut self, mixed: &mut [f32], gains: &mut [f32]) {
+        for (frame, total_gain) in mixed.chunks_exact_mut(2).zip(gains.iter_mut()) {
+            let gain = self.fade.next();
+
+            for sample in frame {
+                *sample += self.buffer.pop_front().unwrap_or(0) as f32 * gain;
+            }
+
+            *total_gain += gain;
+        }
+    }
+}
+
+/* opens the file of a track for a device rate */
+type Opener = fn(&Path, i32) -> Result<MusicStream>;
+
+struct Decoder {
+    soundtrack: Soundtrack,
+    open: Opener,
+    freq: i32,
+    ring: Producer,
+    errors: Sender<Error>,
+    status: Arc<Status>,
+    /* the track the game asked for */
+    stream: Option<MusicStream>,
+    /* the previous one, while it fades out */
+    fading: Option<MusicStream>,
+    /* mixed samples that didn't fit into the ring yet */
+    pending: VecDeque<i16>,
+    fade_out_frames: usize,
+    crossfade_frames: usize,
+    /* one per context, so each keeps its own round */
+    playlists: HashMap<MusicContext, Playlist>,
+    context: Option<MusicContext>,
+    enabled_tracks: usize,
+}
+
+impl Decoder {
+    fn new(soundtrack: Soundtrack, freq: i32, ring: Producer, errors: Sender<Error>, status: Arc<Status>) -> Self {
+        Self {
+            soundtrack,
+            open: open_file,
+            freq,
+            ring,
+            errors,
+            status,
+            stream: None,
+            fading: None,
+            pending: VecDeque::new(),
+            fade_out_frames: 0,
+            crossfade_frames: 0,
+            playlists: HashMap::new(),
+            context: None,
+            enabled_tracks: 0,
+        }
+    }
+
+    fn run(mut self, commands: Receiver<Command>) {
+        loop {
+            match self.next_command(&commands) {
+                Some(Command::Quit) => return,
+                Some(command) => {
+                    self.handle(command);
+                    self.status.pending.fetch_sub(1, Ordering::Release);
+                },
+                None => self.fill(),
+            }
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        self.stream.is_none() && self.fading.is_none() && self.pending.is_empty()
+    }
+
+    fn next_command(&self, commands: &Receiver<Command>) -> Option<Command> {
+        /* nothing to decode, sleep until the game wants music */
+        if self.is_idle() {
+            return Some(commands.recv().unwrap_or(Command::Quit));
+        }
+
+        /* left over samples mean the ring was full, give the callback time */
+        if !self.pending.is_empty() {
+            return match commands.recv_timeout(FULL_WAIT) {
+                Ok(command) => Some(command),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => Some(Command::Quit),
+            };
+        }
+
+        match commands.try_recv() {
+            Ok(command) => Some(command),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Command::Quit),
+        }
+    }
+
+    fn handle(&mut self, command: Command) {
+        match command {
+            Command::Play { music_mode, enabled_tracks } => self.play(music_mode, enabled_tracks),
+            Command::Stop => self.stop(),
+            Command::Quit => (),
+        }
+
+        self.status.playing.store(self.stream.is_some(), Ordering::Relaxed);
+    }
+
+    fn play(&mut self, music_mode: isize, enabled_tracks: usize) {
+        let context = MusicContext::from_mode(music_mode);
+        /* the jump ends in a fight, every other switch is as sudden as in the original */
+        let crossfade = self.crossfade_frames > 0
+            && self.context == Some(MusicContext::Hyperspace)
+            && context == Some(MusicContext::Battle);
+
+        self.context = context;
+        self.enabled_tracks = enabled_tracks;
+
+        let track = match context {
+            Some(context) => self.next_track(context),
+            /* a track number the game itself never asks for */
+            None => usize::try_from(music_mode).ok().and_then(|slot| self.soundtrack.track_in_slot(slot)),
+        };
+
+        let track = match track {
+            Some(track) => track,
+            None => {
+                debug!("no music for mode {}", music_mode);
+                self.stop();
+                return;
+            }
+        };
+
+        let mut stream = match self.open_track(track) {
+            Ok(stream) => stream,
+            Err(error) => {
+                self.report(error);
+                self.stop();
+                return;
+            }
+        };
+
+        match self.stream.take() {
+            Some(previous) if crossfade => {
+                self.fade_out(previous, self.crossfade_frames);
+                stream.fade = Fade::constant(0.0).towards(1.0, self.crossfade_frames);
+            },
+            /* the game wants the new track now, not after what is still buffered */
+            Some(_) => self.cut(),
+            None => (),
+        }
+
+        self.stream = Some(stream);
+    }
+
+    fn next_track(&mut self, context: MusicContext) -> Option<usize> {
+        let playlist = self.playlists.entry(context).or_default();
+
+        playlist.set_tracks(self.soundtrack.tracks_for(context, self.enabled_tracks));
+        playlist.next(&mut rand::thread_rng())
+    }
+
+    fn open_track(&self, track: usize) -> Result<MusicStream> {
+        match self.soundtrack.path(track) {
+            Some(file_path) => (self.open)(file_path, self.freq),
+            None => Err(Error::Audio(format!("no music track {}", track))),
+        }
+    }
+
+    fn stop(&mut self) {
+        match self.stream.take() {
+            Some(stream) if self.fade_out_frames > 0 => self.fade_out(stream, self.fade_out_frames),
+            _ => self.cut(),
+        }
+    }
+
+    /* anything that was still fading out is cut off */
+    fn fade_out(&mut self, mut stream: MusicStream, frames: usize) {
+        stream.fade = stream.fade.towards(0.0, frames);
+        self.fading = Some(stream);
+    }
+
+    fn cut(&mut self) {
+        self.stream = None;
+        self.fading = None;
+        self.pending.clear();
+        self.ring.flush();
+    }
+
+    /* the next track is opened right away, so there is no gap between them */
+    fn end_of_stream(&mut self) {
+        debug!("ogg stream ended");
+
+        self.stream = None;
+
+        if self.context == Some(MusicContext::Continuous) {
+            match self.next_track(MusicContext::Continuous).map(|track| self.open_track(track)) {
+                Some(Ok(stream)) => self.stream = Some(stream),
+                Some(Err(error)) => self.report(error),
+                None => debug!("no music tracks enabled"),
+            }
+        }
+
+        self.status.playing.store(self.stream.is_some(), Ordering::Relaxed);
+    }
+
+    /* decodes at most one packet per stream, and only if the ring has room */
+    fn fill(&mut self) {
+        self.push_pending();
+
+        if !self.pending.is_empty() {
+            return;
+        }
+
+        if matches!(self.stream, Some(ref stream) if stream.buffer.is_empty()) {
+            self.decode_stream();
+        }
+
+        if matches!(self.fading, Some(ref stream) if stream.buffer.is_empty()) {
+            self.decode_fading();
+        }
+
+        self.mix();
+        self.push_pending();
+    }
+
+    fn decode_stream(&mut self) {
+        let result = match self.stream {
+            Some(ref mut stream) => stream.decode(),
+            None => return,
+        };
+
+        match result {
+            Ok(true) => (),
+            Ok(false) => self.end_of_stream(),
+            Err(error) => {
+                self.report(error);
+                self.stream = None;
+                self.status.playing.store(false, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn decode_fading(&mut self) {
+        let result = match self.fading {
+            Some(ref mut stream) => stream.decode(),
+            None => return,
+        };
+
+        if !matches!(result, Ok(true)) {
+            /* nobody is listening anymore, errors don't matter */
+            self.fading = None;
+        }
+    }
+
+    /* mixes as much as all streams have decoded */
+    fn mix(&mut self) {
+        let mut streams: Vec<&mut MusicStream> = self.stream.iter_mut().chain(self.fading.iter_mut()).collect();
+        let len = streams.iter().map(|stream| stream.buffer.len()).min().unwrap_or(0);
+        let mut mixed = vec![0.0; len];
+        let mut gains = vec![0.0; len / 2];
+
+        for stream in streams.iter_mut() {
+            stream.mix_into(&mut mixed, &mut gains);
+        }
+
+        /* while tracks overlap their gains never add up to more than one, so the sum can't clip */
+        for (frame, gain) in mixed.chunks_exact_mut(2).zip(gains) {
+            for sample in frame {
+                *sample /= gain.max(1.0);
+            }
+        }
+
+        self.pending.extend(mixed.iter().map(|sample| sample.round().clamp(-32768.0, 32767.0) as i16));
+
+        if matches!(self.fading, Some(ref stream) if stream.fade.is_silent()) {
+            self.fading = None;
+        }
+    }
+
+    fn push_pending(&mut self) {
+        let (front, back) = self.pending.as_slices();
+        let mut pushed = self.ring.push(front);
+
+        if pushed == front.len() {
+            pushed += self.ring.push(back);
+        }
+
+        self.pending.drain(..pushed);
+    }
+
+    fn report(&self, error: Error) {
+        /* nobody left to tell if the main thread is gone */
+        let _ = self.errors.send(error);
+    }
+}
+
+fn open_file(file_path: &Path, freq: i32) -> Result<MusicStream> {
+	let file = File::open(file_path)
+        .map_err(|error| Error::Music(file_path.to_path_buf(), error.to_string()))?;
+    let reader = OggStreamReader::new(file)
+        .map_err(|error| Error::Music(file_path.to_path_buf(), error.to_string()))?;
+
+    let channels = reader.ident_hdr.audio_channels as usize;
+    let file_freq = reader.ident_hdr.audio_sample_rate;
+
+    debug!("{}: {}Hz, {} channels", file_path.display(), file_freq, channels);
+
+    Ok(MusicStream::new(Box::new(reader), channels, file_freq, freq))
+}
+
+/* the main thread's end of the music thread */
+pub struct Music {
+    commands: Sender<Command>,
+    errors: Receiver<Error>,
+    status: Arc<Status>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Music {
+    pub fn start(music_dir: PathBuf, freq: i32, ring: Producer, config: &GameConfig) -> Result<Self> {
+        let (commands, command_receiver) = mpsc::channel();
+        let (error_sender, errors) = mpsc::channel();
+        let status = Arc::new(Status::default());
+        let frames = |ms: u32| ms as usize * freq as usize / 1000;
+
+        let soundtrack = Soundtrack::load(&music_dir, config.music_manifest.as_deref())
+            .unwrap_or_else(|error| {
+                warn!("{}, playing the original soundtrack", error);
+                Soundtrack::original(&music_dir)
+            });
+
+        let decoder = Decoder {
+            fade_out_frames: frames(config.music_fade_out_ms),
+            crossfade_frames: frames(config.music_crossfade_ms),
+            ..Decoder::new(soundtrack, freq, ring, error_sender, status.clone())
+        };
+
+        let thread = thread::Builder::new()
+            .name("music".into())
+            .spawn(move || decoder.run(command_receiver))?;
+
+        Ok(Self { commands, errors, status, thread: Some(thread) })
+    }
+
+    fn send(&self, command: Command) {
+        self.status.pending.fetch_add(1, Ordering::Relaxed);
+
+        if self.commands.send(command).is_err() {
+            self.status.pending.fetch_sub(1, Ordering::Relaxed);
+            warn!("music thread is gone");
+        }
+    }
+
+    pub fn play(&self, music_mode: isize, enabled_tracks: usize) {
+        self.status.requested.store(true, Ordering::Relaxed);
+        self.send(Command::Play { music_mode, enabled_tracks });
+    }
+
+    pub fn stop(&self) {
+        self.status.requested.store(false, Ordering::Relaxed);
+        self.send(Command::Stop);
+    }
+
+    pub fn is_playing(&self) -> bool {
+        if self.status.pending.load(Ordering::Acquire) > 0 {
+            return self.status.requested.load(Ordering::Relaxed);
+        }
+
+        self.status.playing.load(Ordering::Relaxed)
+    }
+
+    /* what went wrong on the music thread since the last call */
+    pub fn errors(&self) -> Vec<Error> {
+        self.errors.try_iter().collect()
+    }
+}
+
+impl Drop for Music {
+    fn drop(&mut self) {
+        let _ = self.commands.send(Command::Quit);
+
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                error!("music thread panicked");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::ring::{ self, Consumer };
+
+    static FREQ: i32 = 1000;
+    static PACKETS: usize = 3;
+    static PACKET_FRAMES: usize = 10;
+
+    impl Packets for VecDeque<Vec<i16>> {
+        fn next_packet(&mut self) -> Result<Option<Vec<i16>>> {
+            Ok(self.pop_front())
+        }
+    }
+
+    /* every sample of music/NN.ogg is NN + 1, so the tracks can be told apart */
+    fn open_fake(file_path: &Path, freq: i32) -> Result<MusicStream> {
+        let track: i16 = file_path.file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse().ok())
+            .ok_or_else(|| Error::Music(file_path.to_path_buf(), "not a track".into()))?;
+        let packets: VecDeque<Vec<i16>> = (0..PACKETS).map(|_| vec![track + 1; PACKET_FRAMES * 2]).collect();
+
+        Ok(MusicStream::new(Box::new(packets), 2, freq as u32, freq))
+    }
+
+    fn decoder() -> (Decoder, Consumer, Receiver<Error>) {
+        let (producer, consumer) = ring::ring(4096);
+        let (errors, error_receiver) = mpsc::channel();
+        let soundtrack = Soundtrack::original(Path::new("music"));
+        let decoder = Decoder {
+            open: open_fake,
+            ..Decoder::new(soundtrack, FREQ, producer, errors, Arc::new(Status::default()))
+        };
+
+        (decoder, consumer, error_receiver)
+    }
+
+    /* fills like the music thread would and returns what reached the ring */
+    fn drain(decoder: &mut Decoder, consumer: &Consumer, fills: usize) -> Vec<i16> {
+        let mut output = vec!();
+        let mut buffer = [0; 256];
+
+        for _ in 0..fills {
+            decoder.fill();
+
+            loop {
+                match consumer.pop(&mut buffer) {
+                    0 => break,
+                    count => output.extend_from_slice(&buffer[..count]),
+                }
+            }
+        }
+
+        output
+    }
+
+    fn is_playing(decoder: &Decoder) -> bool {
+        decoder.status.playing.load(Ordering::Relaxed)
+    }
+
+    #[test]
+    fn a_track_played_once_ends_and_stops_playing() {
+        let (mut decoder, consumer, _errors) = decoder();
+
+        /* only track 2 is enabled */
+        decoder.handle(Command::Play { music_mode: -2, enabled_tracks: 0x04 });
+        assert!(is_playing(&decoder));
+
+        let output = drain(&mut decoder, &consumer, 20);
+
+        assert!(!is_playing(&decoder));
+        assert!(decoder.is_idle());
+        assert!(output.len() > (PACKETS - 1) * PACKET_FRAMES * 2);
+        assert!(output.iter().all(|&sample| sample == 3));
+    }
+
+    #[test]
+    fn continuous_music_chains_tracks_without_a_gap() {
+        let (mut decoder, consumer, _errors) = decoder();
+
+        decoder.handle(Command::Play { music_mode: -1, enabled_tracks: 0x03 });
+
+        let output = drain(&mut decoder, &consumer, 40);
+
+        assert!(is_playing(&decoder));
+        assert!(output.len() > 4 * PACKETS * PACKET_FRAMES * 2);
+        assert!(output.iter().all(|&sample| sample == 1 || sample == 2));
+        assert!(output.contains(&1) && output.contains(&2));
+    }
+
+    #[test]
+    fn only_the_switch_from_hyperspace_to_battle_crossfades() {
+        let (mut decoder, consumer, _errors) = decoder();
+
+        /* every track at full scale, the overlap has to stay there */
+        decoder.open = |_, freq| {
+            let packets: VecDeque<Vec<i16>> = (0..PACKETS).map(|_| vec![i16::MAX; PACKET_FRAMES * 2]).collect();
+
+            Ok(MusicStream::new(Box::new(packets), 2, freq as u32, freq))
+        };
+        decoder.crossfade_frames = PACKET_FRAMES;
+
+        decoder.handle(Command::Play { music_mode: -3, enabled_tracks: 0x3c });
+        drain(&mut decoder, &consumer, 1);
+        decoder.handle(Command::Play { music_mode: -2, enabled_tracks: 0x3c });
+        assert!(decoder.fading.is_some());
+
+        let output = drain(&mut decoder, &consumer, 2);
+
+        assert!(output.iter().all(|&sample| (i16::MAX - 1..=i16::MAX).contains(&sample)));
+
+        decoder.handle(Command::Play { music_mode: -1, enabled_tracks: 0x3c });
+        assert!(decoder.fading.is_none());
+    }
+
+    #[test]
+    fn a_track_that_fails_to_open_is_reported() {
+        let (mut decoder, _consumer, errors) = decoder();
+
+        decoder.open = |file_path, _| Err(Error::Music(file_path.to_path_buf(), "broken".into()));
+        decoder.handle(Command::Play { music_mode: 1, enabled_tracks: 0xff });
+
+        assert!(!is_playing(&decoder));
+        assert!(decoder.is_idle());
+        assert_eq!(errors.try_iter().count(), 1);
+    }
+
+    #[test]
+    fn is_playing_answers_what_was_asked_until_the_thread_catches_up() {
+        let (mut decoder, consumer, _errors) = decoder();
+        let (commands, command_receiver) = mpsc::channel();
+        let (_error_sender, errors) = mpsc::channel();
+        let music = Music { commands, errors, status: decoder.status.clone(), thread: None };
+
+        let handle_commands = |decoder: &mut Decoder| {
+            for command in command_receiver.try_iter() {
+                decoder.handle(command);
+                decoder.status.pending.fetch_sub(1, Ordering::Release);
+            }
+        };
+
+        assert!(!music.is_playing());
+
+        music.play(-2, 0x04);
+        assert!(music.is_playing());
+        handle_commands(&mut decoder);
+        assert!(music.is_playing());
+
+        /* the game polls this to start the next battle track */
+        drain(&mut decoder, &consumer, 20);
+        assert!(!music.is_playing());
+
+        /* nothing enabled, nothing to play */
+        music.play(-2, 0);
+        assert!(music.is_playing());
+        handle_commands(&mut decoder);
+        assert!(!music.is_playing());
+
+        music.play(-1, 0x01);
+        music.stop();
+        assert!(!music.is_playing());
+        handle_commands(&mut decoder);
+        assert!(!music.is_playing());
+    }
+}
@@ -0,0 +1,94 @@
+/*
+ * Picks the tracks for random play. Every enabled track is played once, in
+ * shuffled order, before any of them comes up again, and a new round never
+ * starts with the track the last one ended with. Tracks are indices into
+ * the soundtrack, there can be any number of them.
+ */
+
+use rand::Rng;
+use rand::seq::SliceRandom;
+
+#[derive(Default)]
+pub struct Playlist {
+    tracks: Vec<usize>,
+    /* what is left of the current round, played from the end */
+    round: Vec<usize>,
+    last: Option<usize>,
+}
+
+impl Playlist {
+    /* the round only starts over if the tracks have changed */
+    pub fn set_tracks(&mut self, tracks: Vec<usize>) {
+        if tracks != self.tracks {
+            self.tracks = tracks;
+            self.round.clear();
+        }
+    }
+
+    /* None if there is nothing to play */
+    pub fn next<R: Rng>(&mut self, rng: &mut R) -> Option<usize> {
+        if self.round.is_empty() {
+            self.round = self.tracks.clone();
+            self.round.shuffle(rng);
+
+            let len = self.round.len();
+
+            if len > 1 && self.round.last() == self.last.as_ref() {
+                self.round.swap(0, len - 1);
+            }
+        }
+
+        let track = self.round.pop()?;
+
+        self.last = Some(track);
+        Some(track)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn empty_mask_plays_nothing() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut playlist = Playlist::default();
+
+        playlist.set_tracks(vec!());
+
+        assert_eq!(playlist.next(&mut rng), None);
+    }
+
+    #[test]
+    fn every_track_once_per_round_without_repeats() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut playlist = Playlist::default();
+        let tracks: Vec<usize> = (0..12).collect();
+        let mut previous = None;
+
+        playlist.set_tracks(tracks.clone());
+
+        for _ in 0..50 {
+            let mut round: Vec<usize> = (0..tracks.len()).map(|_| playlist.next(&mut rng).unwrap()).collect();
+
+            assert_ne!(Some(round[0]), previous);
+            previous = round.last().cloned();
+
+            round.sort();
+            assert_eq!(round, tracks);
+        }
+    }
+
+    #[test]
+    fn a_single_track_repeats() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut playlist = Playlist::default();
+
+        playlist.set_tracks(vec![5]);
+
+        assert_eq!(playlist.next(&mut rng), Some(5));
+        assert_eq!(playlist.next(&mut rng), Some(5));
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::VecDeque;
+
+use sdl2::audio::AudioFormat;
+
+/* bytes per sample and a decoder to the range of an i16 */
+fn decoder(format: AudioFormat) -> (usize, fn(&[u8]) -> f32) {
+    match format {
+        AudioFormat::U8 => (1, |b| (b[0] as f32 - 128.0) * 256.0),
+        AudioFormat::S8 => (1, |b| b[0] as i8 as f32 * 256.0),
+        AudioFormat::U16LSB => (2, |b| u16::from_le_bytes([b[0], b[1]]) as f32 - 32768.0),
+        AudioFormat::U16MSB => (2, |b| u16::from_be_bytes([b[0], b[1]]) as f32 - 32768.0),
+        AudioFormat::S16LSB => (2, |b| i16::from_le_bytes([b[0], b[1]]) as f32),
+        AudioFormat::S16MSB => (2, |b| i16::from_be_bytes([b[0], b[1]]) as f32),
+        AudioFormat::S32LSB => (4, |b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 65536.0),
+        AudioFormat::S32MSB => (4, |b| i32::from_be_bytes([b[0], b[1], b[2], b[3]]) as f32 / 65536.0),
+        AudioFormat::F32LSB => (4, |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) * 32767.0),
+        AudioFormat::F32MSB => (4, |b| f32::from_be_bytes([b[0], b[1], b[2], b[3]]) * 32767.0),
+    }
+}
+
+pub fn format_name(format: AudioFormat) -> &'static str {
+    match format {
+        AudioFormat::U8 => "U8",
+        AudioFormat::S8 => "S8",
+        AudioFormat::U16LSB => "U16LSB",
+        AudioFormat::U16MSB => "U16MSB",
+        AudioFormat::S16LSB => "S16LSB",
+        AudioFormat::S16MSB => "S16MSB",
+        AudioFormat::S32LSB => "S32LSB",
+        AudioFormat::S32MSB => "S32MSB",
+        AudioFormat::F32LSB => "F32LSB",
+        AudioFormat::F32MSB => "F32MSB",
+    }
+}
+
+/* decodes a wav buffer of any format, the channels are mixed down to mono */
+pub fn to_mono(format: AudioFormat, channels: u8, buffer: &[u8]) -> Vec<f32> {
+    let (sample_size, decode) = decoder(format);
+    let channels = channels.max(1) as usize;
+
+    buffer.chunks_exact(sample_size * channels)
+        .map(|frame| {
+            let sum: f32 = frame.chunks_exact(sample_size).map(decode).sum();
+
+            sum / channels as f32
+        })
+        .collect()
+}
+
+/*
+ * Good enough for sound effects: when going down in rate every output
+ * sample is the average of the input samples it covers, which keeps most
+ * of the aliasing out. Going up is a linear interpolation.
+ */
+pub fn resample(samples: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
+    if from_rate == to_rate || samples.is_empty() || from_rate == 0 || to_rate == 0 {
+        return samples.to_vec();
+    }
+
+    let ratio = from_rate as f64 / to_rate as f64;
+    let len = (samples.len() as f64 / ratio).floor() as usize;
+
+    (0..len)
+        .map(|n| {
+            let start = n as f64 * ratio;
+
+            if ratio > 1.0 {
+                let first = start as usize;
+                let last = (((n + 1) as f64 * ratio) as usize).min(samples.len()).max(first + 1);
+                let window = &samples[first..last];
+
+                window.iter().sum::<f32>() / window.len() as f32
+            } else {
+                let index = start as usize;
+                let fraction = (start - index as f64) as f32;
+                let next = samples.get(index + 1).cloned().unwrap_or(samples[index]);
+
+                samples[index] + (next - samples[index]) * fraction
+            }
+        })
+        .collect()
+}
+
+pub fn to_i16(samples: &[f32]) -> Vec<i16> {
+    samples.iter().map(|sample| sample.round().clamp(-32768.0, 32767.0) as i16).collect()
+}
+
+/*
+ * Linear interpolation over a stream that arrives in packets, for the
+ * music. The output is always interleaved stereo, mono input goes to both
+ * sides and channels past the second are ignored. The last input frame is
+ * kept between packets so there is no click at the packet boundaries.
+ */
+pub struct StreamResampler {
+    step: f64,
+    position: f64,
+    frames: Vec<[f32; 2]>,
+}
+
+impl StreamResampler {
+    pub fn new(from_rate: u32, to_rate: u32) -> Self {
+        let step = if from_rate == 0 || to_rate == 0 { 1.0 } else { from_rate as f64 / to_rate as f64 };
+
+        Self {
+            step,
+            position: 0.0,
+            frames: vec!(),
+        }
+    }
+
+    /* adds the resampled frames of an interleaved packet to output */
+    pub fn process(&mut self, input: &[i16], channels: usize, output: &mut VecDeque<i16>) {
+        let channels = channels.max(1);
+
+        self.frames.extend(input.chunks_exact(channels).map(|frame| {
+            let left = frame[0] as f32;
+            let right = frame.get(1).map_or(left, |&right| right as f32);
+
+            [left, right]
+        }));
+
+        while self.position + 1.0 < self.frames.len() as f64 {
+            let index = self.position as usize;
+            let fraction = (self.position - index as f64) as f32;
+            let (current, next) = (self.frames[index], self.frames[index + 1]);
+
+            for side in 0..2 {
+                let sample = current[side] + (next[side] - current[side]) * fraction;
+
+                output.push_back(sample.round().clamp(-32768.0, 32767.0) as i16);
+            }
+
+            self.position += self.step;
+        }
+
+        /* the frames before the current position are not needed anymore */
+        let consumed = (self.position as usize).min(self.frames.len());
+
+        self.frames.drain(..consumed);
+        self.position -= consumed as f64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn downsampling_averages_the_window_of_each_output_sample() {
+        let samples = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0];
+
+        assert_eq!(resample(&samples, 2, 1), vec![1.5, 3.5, 5.5]);
+        assert_eq!(resample(&samples, 3, 2), vec![1.0, 2.5, 4.0, 5.5]);
+    }
+
+    #[test]
+    fn upsampling_interpolates_and_holds_the_last_sample() {
+        assert_eq!(resample(&[0.0, 10.0, 20.0], 1, 2), vec![0.0, 5.0, 10.0, 15.0, 20.0, 20.0]);
+        assert_eq!(resample(&[0.0, 10.0], 22050, 22050), vec![0.0, 10.0]);
+    }
+
+    #[test]
+    fn samples_are_rounded_and_clipped_to_i16() {
+        assert_eq!(to_i16(&[0.4, -0.6, 16383.5, 40000.0, -40000.0]), vec![0, -1, 16384, 32767, -32768]);
+    }
+
+    #[test]
+    fn formats_decode_to_the_range_of_an_i16() {
+        let s32: Vec<u8> = [100 << 16, i32::MAX, i32::MIN].iter().flat_map(|sample| sample.to_le_bytes().to_vec()).collect();
+        let f32: Vec<u8> = [0.5f32, 2.0, -2.0].iter().flat_map(|sample| sample.to_be_bytes().to_vec()).collect();
+
+        assert_eq!(to_i16(&to_mono(AudioFormat::S32LSB, 1, &s32)), vec![100, 32767, -32768]);
+        assert_eq!(to_i16(&to_mono(AudioFormat::F32MSB, 1, &f32)), vec![16384, 32767, -32768]);
+        assert_eq!(to_i16(&to_mono(AudioFormat::U8, 1, &[0, 128, 255])), vec![-32768, 0, 32512]);
+    }
+
+    #[test]
+    fn stereo_is_mixed_down_to_mono() {
+        let frames: Vec<u8> = [100i16, 300, -200, 0].iter().flat_map(|sample| sample.to_le_bytes().to_vec()).collect();
+
+        assert_eq!(to_mono(AudioFormat::S16LSB, 2, &frames), vec![200.0, -100.0]);
+    }
+
+    #[test]
+    fn packets_resample_like_one_continuous_stream() {
+        let mut whole = VecDeque::new();
+        let mut split = VecDeque::new();
+        let mut resampler = StreamResampler::new(1, 2);
+
+        StreamResampler::new(1, 2).process(&[0, 100, 200, 300], 1, &mut whole);
+        resampler.process(&[0, 100], 1, &mut split);
+        resampler.process(&[200, 300], 1, &mut split);
+
+        assert_eq!(whole, vec![0, 0, 50, 50, 100, 100, 150, 150, 200, 200, 250, 250]);
+        assert_eq!(split, whole);
+    }
+}
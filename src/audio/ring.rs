@@ -0,0 +1,173 @@
+/*
+ * A ring of samples with one producer and one consumer, the music thread
+ * writes and the audio callback reads. Neither side ever takes a lock or
+ * waits for the other: a full ring makes the producer come back later,
+ * an empty one gives the callback less than it asked for.
+ */
+
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicBool, AtomicI16, AtomicUsize, Ordering };
+
+struct Shared {
+    samples: Box<[AtomicI16]>,
+    /* positions only ever grow, wrapping, the slot is position % capacity */
+    read: AtomicUsize,
+    write: AtomicUsize,
+    /* set by the producer, the consumer skips ahead to flush_to */
+    flush: AtomicBool,
+    flush_to: AtomicUsize,
+}
+
+impl Shared {
+    fn capacity(&self) -> usize {
+        self.samples.len()
+    }
+
+    fn slot(&self, position: usize) -> &AtomicI16 {
+        &self.samples[position % self.capacity()]
+    }
+}
+
+pub struct Producer {
+    shared: Arc<Shared>,
+}
+
+pub struct Consumer {
+    shared: Arc<Shared>,
+}
+
+pub fn ring(capacity: usize) -> (Producer, Consumer) {
+    let shared = Arc::new(Shared {
+        samples: (0..capacity.max(1)).map(|_| AtomicI16::new(0)).collect(),
+        read: AtomicUsize::new(0),
+        write: AtomicUsize::new(0),
+        flush: AtomicBool::new(false),
+        flush_to: AtomicUsize::new(0),
+    });
+
+    (Producer { shared: shared.clone() }, Consumer { shared })
+}
+
+impl Producer {
+    /* writes as much of samples as fits and returns how much that was */
+    pub fn push(&self, samples: &[i16]) -> usize {
+        let shared = &*self.shared;
+        let write = shared.write.load(Ordering::Relaxed);
+        let read = shared.read.load(Ordering::Acquire);
+        let free = shared.capacity() - write.wrapping_sub(read);
+        let count = samples.len().min(free);
+
+        for (offset, sample) in samples[..count].iter().enumerate() {
+            shared.slot(write.wrapping_add(offset)).store(*sample, Ordering::Relaxed);
+        }
+
+        shared.write.store(write.wrapping_add(count), Ordering::Release);
+        count
+    }
+
+    /* everything pushed so far is dropped instead of played */
+    pub fn flush(&self) {
+        let shared = &*self.shared;
+
+        shared.flush_to.store(shared.write.load(Ordering::Relaxed), Ordering::Relaxed);
+        shared.flush.store(true, Ordering::Release);
+    }
+}
+
+impl Consumer {
+    /* fills the start of dest, returns how many samples there were */
+    pub fn pop(&self, dest: &mut [i16]) -> usize {
+        let shared = &*self.shared;
+        let mut read = shared.read.load(Ordering::Relaxed);
+
+        if shared.flush.swap(false, Ordering::Acquire) {
+            let flush_to = shared.flush_to.load(Ordering::Relaxed);
+
+            /* an older flush we have already read past is ignored */
+            if flush_to.wrapping_sub(read) <= shared.capacity() {
+                read = flush_to;
+            }
+        }
+
+        let write = shared.write.load(Ordering::Acquire);
+        let count = dest.len().min(write.wrapping_sub(read));
+
+        for (offset, sample) in dest[..count].iter_mut().enumerate() {
+            *sample = shared.slot(read.wrapping_add(offset)).load(Ordering::Relaxed);
+        }
+
+        shared.read.store(read.wrapping_add(count), Ordering::Release);
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn push_stops_when_full() {
+        let (producer, consumer) = ring(4);
+        let mut output = [0; 4];
+
+        assert_eq!(producer.push(&[1, 2, 3, 4, 5, 6]), 4);
+        assert_eq!(producer.push(&[7]), 0);
+        assert_eq!(consumer.pop(&mut output[..3]), 3);
+        assert_eq!(output[..3], [1, 2, 3]);
+
+        assert_eq!(producer.push(&[5, 6, 7]), 3);
+        assert_eq!(consumer.pop(&mut output), 4);
+        assert_eq!(output, [4, 5, 6, 7]);
+        assert_eq!(consumer.pop(&mut output), 0);
+    }
+
+    #[test]
+    fn flush_drops_what_was_pushed_before() {
+        let (producer, consumer) = ring(8);
+        let mut output = [0; 8];
+
+        producer.push(&[1, 2, 3]);
+        producer.flush();
+        producer.push(&[4, 5]);
+
+        assert_eq!(consumer.pop(&mut output), 2);
+        assert_eq!(output[..2], [4, 5]);
+    }
+
+    #[test]
+    fn samples_arrive_in_order_across_threads() {
+        let (producer, consumer) = ring(64);
+        let total = 100_000;
+
+        let writer = thread::spawn(move || {
+            let samples: Vec<i16> = (0..total).map(|n| (n % 30000) as i16).collect();
+            let mut written = 0;
+
+            while written < samples.len() {
+                match producer.push(&samples[written..(written + 17).min(samples.len())]) {
+                    0 => thread::yield_now(),
+                    count => written += count,
+                }
+            }
+        });
+
+        let mut received = 0;
+        let mut output = [0; 23];
+
+        while received < total {
+            let count = consumer.pop(&mut output);
+
+            if count == 0 {
+                thread::yield_now();
+            }
+
+            for sample in &output[..count] {
+                assert_eq!(*sample, (received % 30000) as i16);
+                received += 1;
+            }
+        }
+
+        writer.join().unwrap();
+    }
+}
@@ -0,0 +1,186 @@
+/*
+ * Audio output without a sound device. The mixer is run from the emulation
+ * thread once per VBL, for exactly as much audio as the VBL is long, so the
+ * result follows emulated time however fast the emulation runs. What comes
+ * out is written to a WAV file, or dropped if there is none.
+ */
+
+use std::fs::File;
+use std::io::{ self, BufWriter, Seek, SeekFrom, Write };
+use std::path::{ Path, PathBuf };
+use std::time::Duration;
+
+use crate::error::Result;
+
+use super::backend::{ Backend, EngineLock };
+use super::engine::Engine;
+
+static WAV_HEADER_LEN: u32 = 44;
+
+/* 16-bit PCM, the sizes are filled in when the file is finished */
+pub struct WavWriter {
+    path: PathBuf,
+    file: BufWriter<File>,
+    freq: u32,
+    channels: u16,
+    data_len: u32,
+}
+
+impl WavWriter {
+    pub fn create(path: &Path, freq: u32, channels: u16) -> Result<Self> {
+        let mut writer = Self {
+            path: path.to_path_buf(),
+            file: BufWriter::new(File::create(path)?),
+            freq,
+            channels,
+            data_len: 0,
+        };
+
+        writer.write_header()?;
+        Ok(writer)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let block_align = self.channels * 2;
+
+        self.file.write_all(b"RIFF")?;
+        self.file.write_all(&(WAV_HEADER_LEN - 8 + self.data_len).to_le_bytes())?;
+        self.file.write_all(b"WAVEfmt ")?;
+        self.file.write_all(&16u32.to_le_bytes())?;
+        self.file.write_all(&1u16.to_le_bytes())?; // PCM
+        self.file.write_all(&self.channels.to_le_bytes())?;
+        self.file.write_all(&self.freq.to_le_bytes())?;
+        self.file.write_all(&(self.freq * block_align as u32).to_le_bytes())?;
+        self.file.write_all(&block_align.to_le_bytes())?;
+        self.file.write_all(&16u16.to_le_bytes())?;
+        self.file.write_all(b"data")?;
+        self.file.write_all(&self.data_len.to_le_bytes())
+    }
+
+    pub fn write(&mut self, samples: &[i16]) -> io::Result<()> {
+        for sample in samples {
+            self.file.write_all(&sample.to_le_bytes())?;
+        }
+
+        self.data_len = self.data_len.saturating_add(samples.len() as u32 * 2);
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(0))?;
+        self.write_header()?;
+        self.file.flush()
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        match self.finish() {
+            Ok(()) => info!("wrote {} bytes of audio to {}", self.data_len, self.path.display()),
+            Err(error) => error!("unable to finish {}: {}", self.path.display(), error),
+        }
+    }
+}
+
+pub struct Sink {
+    engine: Engine,
+    writer: Option<WavWriter>,
+    freq: u32,
+    channels: usize,
+    buffer: Vec<i16>,
+    /* frames owed to the output, the fraction carries over to the next VBL */
+    due_frames: f64,
+    paused: bool,
+}
+
+impl Sink {
+    /* mixes in the format the engine was made for */
+    pub fn new(engine: Engine, writer: Option<WavWriter>) -> Self {
+        let format = engine.format();
+        let freq = format.freq as u32;
+        let channels = format.channels.max(1) as usize;
+        let samples = format.samples.max(1) as usize;
+
+        Self {
+            engine,
+            writer,
+            freq,
+            channels,
+            buffer: vec![0; samples * channels],
+            due_frames: 0.0,
+            paused: false,
+        }
+    }
+}
+
+impl Backend for Sink {
+    fn lock(&mut self) -> EngineLock<'_> {
+        Box::new(&mut self.engine)
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /* mixes the audio for an emulated time span */
+    fn advance(&mut self, elapsed: Duration) {
+        if self.paused {
+            return;
+        }
+
+        self.due_frames += elapsed.as_secs_f64() * self.freq as f64;
+
+        while self.due_frames >= 1.0 {
+            let frames = (self.due_frames as usize).min(self.buffer.len() / self.channels);
+            let buffer = &mut self.buffer[..frames * self.channels];
+
+            self.engine.mix(buffer);
+            self.due_frames -= frames as f64;
+
+            let result = match self.writer {
+                Some(ref mut writer) => writer.write(buffer),
+                None => Ok(()),
+            };
+
+            if let Err(error) = result {
+                /* keep mixing, the game shouldn't notice */
+                error!("unable to write audio: {}", error);
+                self.writer = None;
+            }
+        }
+    }
+
+    fn has_device(&self) -> bool {
+        false
+    }
+
+    fn into_engine(self: Box<Self>) -> Engine {
+        self.engine
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn wav_header_is_rewritten_with_the_sizes() {
+        let path = std::env::temp_dir().join(format!("frontier-sink-test-{}.wav", std::process::id()));
+        let mut writer = WavWriter::create(&path, 22050, 2).unwrap();
+
+        writer.write(&[1, -1, 2, -2]).unwrap();
+        drop(writer);
+
+        let wav = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(wav.len(), 44 + 8);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(wav[4..8], 44u32.to_le_bytes());
+        assert_eq!(wav[24..28], 22050u32.to_le_bytes());
+        assert_eq!(wav[28..32], (22050u32 * 4).to_le_bytes());
+        assert_eq!(wav[40..44], 8u32.to_le_bytes());
+        assert_eq!(wav[44..48], [1, 0, 0xff, 0xff]);
+    }
+}
@@ -0,0 +1,276 @@
+/*
+ * Where each of the 33 sound effects comes from, whether it loops and how
+ * loud it is. A sound pack is a directory with replacement sfx_NN.wav
+ * files and optionally a sounds.toml:
+ *
+ *     [[sample]]
+ *     index = 19
+ *     file = "hyperspace.wav"
+ *     loop_start = 22050
+ *     loop_end = 88200
+ *     gain = 0.8
+ *
+ * Loop points are in frames of the wav file, the end is exclusive and
+ * defaults to the end of the sample. "loop = false" switches looping off.
+ * Without loop points in the manifest they are taken from the smpl chunk
+ * of the wav, and failing that from the original game.
+ */
+
+use std::convert::TryInto;
+use std::fs;
+use std::path::{ Path, PathBuf };
+
+use serde::Deserialize;
+
+use crate::error::{ Error, Result };
+
+static MANIFEST_NAME: &str = "sounds.toml";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoopPoints {
+    pub start: u32,
+    pub end: Option<u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SampleEntry {
+    index: usize,
+    file: Option<PathBuf>,
+    loop_start: Option<u32>,
+    loop_end: Option<u32>,
+    #[serde(rename = "loop")]
+    looping: Option<bool>,
+    gain: Option<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    #[serde(rename = "sample", default)]
+    samples: Vec<SampleEntry>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Looping {
+    Off,
+    Points(LoopPoints),
+    /* the smpl chunk if there is one, otherwise what the game did */
+    Auto,
+}
+
+pub struct Sample {
+    pub file: PathBuf,
+    pub gain: f32,
+    index: usize,
+    looping: Looping,
+}
+
+impl Sample {
+    /* in frames at the rate of the wav file */
+    pub fn loop_points(&self, freq: i32) -> Option<LoopPoints> {
+        let wav = match self.looping {
+            Looping::Auto => fs::read(&self.file).ok(),
+            _ => None,
+        };
+
+        self.loop_points_in(wav.as_deref(), freq)
+    }
+
+    /* wav is what the file holds, it only matters for Looping::Auto */
+    fn loop_points_in(&self, wav: Option<&[u8]>, freq: i32) -> Option<LoopPoints> {
+        match self.looping {
+            Looping::Off => None,
+            Looping::Points(points) => Some(points),
+            Looping::Auto => wav.and_then(parse_smpl_loop).or_else(|| original_loop(self.index, freq)),
+        }
+    }
+}
+
+/* the samples the game looped: 19 is hyperspace, 23 noise */
+fn original_loop(index: usize, freq: i32) -> Option<LoopPoints> {
+    match index {
+        19 => Some(LoopPoints { start: freq as u32 / 2, end: None }), // loop to about 0.5 sec in
+        23 => Some(LoopPoints { start: 0, end: None }),
+        _ => None,
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    bytes.get(offset..offset + 4).and_then(|bytes| bytes.try_into().ok()).map(u32::from_le_bytes)
+}
+
+/* the first loop of the smpl chunk, its end is inclusive there */
+fn parse_smpl_loop(wav: &[u8]) -> Option<LoopPoints> {
+    if wav.get(0..4) != Some(b"RIFF") || wav.get(8..12) != Some(b"WAVE") {
+        return None;
+    }
+
+    let mut offset = 12;
+
+    while let Some(size) = read_u32(wav, offset + 4) {
+        let data = offset + 8;
+
+        if wav.get(offset..offset + 4) == Some(b"smpl") {
+            /* 36 bytes of header, the loop count is at 28, each loop takes 24 */
+            if read_u32(wav, data + 28)? == 0 {
+                return None;
+            }
+
+            let start = read_u32(wav, data + 36 + 8)?;
+            let end = read_u32(wav, data + 36 + 12)?;
+
+            return Some(LoopPoints { start, end: Some(end.saturating_add(1)) });
+        }
+
+        /* chunks are padded to an even size */
+        offset = data + size as usize + (size as usize & 1);
+    }
+
+    None
+}
+
+pub struct SoundPack {
+    sfx_dir: PathBuf,
+    pack_dir: Option<PathBuf>,
+    entries: Vec<SampleEntry>,
+}
+
+impl SoundPack {
+    /* the original sounds, from sfx_dir */
+    pub fn original(sfx_dir: &Path) -> Self {
+        Self { sfx_dir: sfx_dir.to_path_buf(), pack_dir: None, entries: vec!() }
+    }
+
+    /* files the pack doesn't have still come from sfx_dir */
+    pub fn load(sfx_dir: &Path, pack_dir: &Path) -> Result<Self> {
+        let manifest_path = pack_dir.join(MANIFEST_NAME);
+        let mut pack = Self { sfx_dir: sfx_dir.to_path_buf(), pack_dir: Some(pack_dir.to_path_buf()), entries: vec!() };
+
+        if !pack_dir.is_dir() {
+            return Err(Error::Audio(format!("sound pack {} is not a directory", pack_dir.display())));
+        }
+
+        if manifest_path.is_file() {
+            let content = fs::read_to_string(&manifest_path)
+                .map_err(|error| Error::Audio(format!("{}: {}", manifest_path.display(), error)))?;
+            let manifest: Manifest = toml::from_str(&content)
+                .map_err(|error| Error::Audio(format!("{}: {}", manifest_path.display(), error)))?;
+
+            pack.entries = manifest.samples;
+        }
+
+        info!("sound pack {}, {} samples in the manifest", pack_dir.display(), pack.entries.len());
+
+        Ok(pack)
+    }
+
+    pub fn sample(&self, index: usize) -> Sample {
+        let file_name = format!("sfx_{:02}.wav", index);
+        /* the last entry wins, like in the config */
+        let entry = self.entries.iter().rev().find(|entry| entry.index == index);
+
+        let pack_file = self.pack_dir.as_ref().and_then(|pack_dir| {
+            match entry.and_then(|entry| entry.file.as_ref()) {
+                Some(file) if !pack_dir.join(file).is_file() => {
+                    warn!("{} not found, using the original sfx {:02}", pack_dir.join(file).display(), index);
+                    None
+                },
+                Some(file) => Some(pack_dir.join(file)),
+                None => Some(pack_dir.join(&file_name)).filter(|file| file.is_file()),
+            }
+        });
+
+        let looping = match entry {
+            Some(SampleEntry { looping: Some(false), .. }) => Looping::Off,
+            Some(SampleEntry { loop_start: Some(start), loop_end, .. }) => {
+                Looping::Points(LoopPoints { start: *start, end: *loop_end })
+            },
+            _ => Looping::Auto,
+        };
+
+        Sample {
+            file: pack_file.unwrap_or_else(|| self.sfx_dir.join(&file_name)),
+            gain: entry.and_then(|entry| entry.gain).unwrap_or(1.0).max(0.0),
+            index,
+            looping,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wav_with_chunk(id: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut wav = b"RIFF\0\0\0\0WAVE".to_vec();
+
+        wav.extend(b"fmt \x03\0\0\0abc\0");
+        wav.extend(id);
+        wav.extend(&(data.len() as u32).to_le_bytes());
+        wav.extend(data);
+        wav
+    }
+
+    fn smpl_with_loop(start: u32, end: u32) -> Vec<u8> {
+        let mut smpl = vec![0; 36 + 24];
+
+        smpl[28..32].copy_from_slice(&1u32.to_le_bytes());
+        smpl[44..48].copy_from_slice(&start.to_le_bytes());
+        smpl[48..52].copy_from_slice(&end.to_le_bytes());
+        smpl
+    }
+
+    #[test]
+    fn smpl_loops_are_read_with_an_exclusive_end() {
+        let smpl = smpl_with_loop(100, 199);
+
+        assert_eq!(parse_smpl_loop(&wav_with_chunk(b"smpl", &smpl)), Some(LoopPoints { start: 100, end: Some(200) }));
+        assert_eq!(parse_smpl_loop(&wav_with_chunk(b"smpl", &[0; 36])), None);
+        assert_eq!(parse_smpl_loop(&wav_with_chunk(b"data", &smpl)), None);
+        assert_eq!(parse_smpl_loop(b"RIFF"), None);
+    }
+
+    #[test]
+    fn the_game_looped_hyperspace_and_noise() {
+        assert_eq!(original_loop(19, 22050), Some(LoopPoints { start: 11025, end: None }));
+        assert_eq!(original_loop(23, 44100), Some(LoopPoints { start: 0, end: None }));
+        assert_eq!(original_loop(5, 22050), None);
+    }
+
+    #[test]
+    fn auto_looping_prefers_the_smpl_chunk_over_the_game() {
+        let pack = SoundPack::original(Path::new("sfx"));
+        let with_loop = wav_with_chunk(b"smpl", &smpl_with_loop(100, 199));
+        let without_loop = wav_with_chunk(b"data", &[0; 8]);
+
+        assert_eq!(pack.sample(19).looping, Looping::Auto);
+        assert_eq!(pack.sample(19).loop_points_in(Some(&with_loop), 22050), Some(LoopPoints { start: 100, end: Some(200) }));
+        assert_eq!(pack.sample(5).loop_points_in(Some(&with_loop), 22050), Some(LoopPoints { start: 100, end: Some(200) }));
+        assert_eq!(pack.sample(19).loop_points_in(Some(&without_loop), 22050), Some(LoopPoints { start: 11025, end: None }));
+        assert_eq!(pack.sample(19).loop_points_in(None, 22050), Some(LoopPoints { start: 11025, end: None }));
+        assert_eq!(pack.sample(5).loop_points_in(Some(&without_loop), 22050), None);
+        assert_eq!(pack.sample(5).file, Path::new("sfx/sfx_05.wav"));
+    }
+
+    #[test]
+    fn manifest_entries_override_loops_and_gain() {
+        let manifest: Manifest = toml::from_str(r#"
+            [[sample]]
+            index = 19
+            loop = false
+
+            [[sample]]
+            index = 4
+            loop_start = 10
+            loop_end = 20
+            gain = 0.5
+        "#).unwrap();
+
+        let pack = SoundPack { entries: manifest.samples, ..SoundPack::original(Path::new("sfx")) };
+
+        assert_eq!(pack.sample(19).loop_points(22050), None);
+        assert_eq!(pack.sample(4).loop_points(22050), Some(LoopPoints { start: 10, end: Some(20) }));
+        assert_eq!(pack.sample(4).gain, 0.5);
+        assert_eq!(pack.sample(23).gain, 1.0);
+    }
+}
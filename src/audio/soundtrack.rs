@@ -0,0 +1,204 @@
+/*
+ * Which music files play when. Without a manifest this is the original
+ * soundtrack, music/00.ogg to music/07.ogg. A manifest (music/music.toml,
+ * or music_manifest in the config) can list any number of files instead:
+ *
+ *     [[track]]
+ *     file = "first_contact.ogg"
+ *     contexts = ["battle", "hyperspace"]
+ *     slot = 2
+ *
+ * Files are relative to the manifest. A track with a slot is switched on
+ * and off with the game's music option for that track, the others are
+ * always enabled.
+ */
+
+use std::fs;
+use std::path::{ Path, PathBuf };
+
+use serde::Deserialize;
+
+use crate::error::{ Error, Result };
+
+static MANIFEST_NAME: &str = "music.toml";
+
+/* the game only has 8 tracks it can switch on and off */
+static SLOTS: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MusicContext {
+    Intro,
+    Docking,
+    Battle,
+    Hyperspace,
+    Reward,
+    /* the "continuous music" option, one track after the other */
+    Continuous,
+}
+
+impl MusicContext {
+    /* what the game means with d0 of Call_PlayMusic, see L70_StartMusic */
+    pub fn from_mode(music_mode: isize) -> Option<Self> {
+        match music_mode {
+            -1 => Some(MusicContext::Continuous),
+            -2 => Some(MusicContext::Battle),
+            -3 => Some(MusicContext::Hyperspace),
+            1 => Some(MusicContext::Intro),
+            6 => Some(MusicContext::Docking),
+            7 => Some(MusicContext::Reward),
+            _ => None,
+        }
+    }
+
+    /* the game picks these tracks itself, whatever the options say */
+    fn ignores_mask(self) -> bool {
+        matches!(self, MusicContext::Intro | MusicContext::Docking | MusicContext::Reward)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Track {
+    file: PathBuf,
+    contexts: Vec<MusicContext>,
+    slot: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    #[serde(rename = "track", default)]
+    tracks: Vec<Track>,
+}
+
+pub struct Soundtrack {
+    tracks: Vec<Track>,
+}
+
+impl Soundtrack {
+    /* the tracks of the original game, the way it used them */
+    pub fn original(music_dir: &Path) -> Self {
+        use MusicContext::*;
+
+        let tracks = (0..SLOTS)
+            .map(|slot| {
+                /* the blue danube and the reward music are never picked at random */
+                let contexts = match slot {
+                    1 => vec![Intro, Battle, Hyperspace, Continuous],
+                    6 => vec![Docking],
+                    7 => vec![Reward],
+                    _ => vec![Battle, Hyperspace, Continuous],
+                };
+
+                Track { file: music_dir.join(format!("{:02}.ogg", slot)), contexts, slot: Some(slot) }
+            })
+            .collect();
+
+        Self { tracks }
+    }
+
+    /* the manifest if there is one, the original soundtrack otherwise */
+    pub fn load(music_dir: &Path, manifest: Option<&Path>) -> Result<Self> {
+        let default_manifest = music_dir.join(MANIFEST_NAME);
+
+        let path = match manifest {
+            Some(path) => path,
+            None if default_manifest.is_file() => &default_manifest,
+            None => return Ok(Self::original(music_dir)),
+        };
+
+        let content = fs::read_to_string(path)
+            .map_err(|error| Error::Music(path.to_path_buf(), error.to_string()))?;
+        let manifest: Manifest = toml::from_str(&content)
+            .map_err(|error| Error::Music(path.to_path_buf(), error.to_string()))?;
+
+        let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let mut tracks = vec!();
+
+        for mut track in manifest.tracks {
+            track.file = base_dir.join(&track.file);
+
+            if !track.file.is_file() {
+                warn!("{}: {} not found, skipped", path.display(), track.file.display());
+                continue;
+            }
+
+            if matches!(track.slot, Some(slot) if slot >= SLOTS) {
+                warn!("{}: slot of {} must be below {}, ignored", path.display(), track.file.display(), SLOTS);
+                track.slot = None;
+            }
+
+            tracks.push(track);
+        }
+
+        info!("{} music tracks from {}", tracks.len(), path.display());
+
+        Ok(Self { tracks })
+    }
+
+    /* the tracks for a context, with the mask of the game's music options */
+    pub fn tracks_for(&self, context: MusicContext, enabled_slots: usize) -> Vec<usize> {
+        self.tracks.iter().enumerate()
+            .filter(|(_, track)| track.contexts.contains(&context))
+            .filter(|(_, track)| match track.slot {
+                Some(slot) if !context.ignores_mask() => enabled_slots & (1 << slot) != 0,
+                _ => true,
+            })
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /* the track the game asks for by number, if it isn't one of the contexts */
+    pub fn track_in_slot(&self, slot: usize) -> Option<usize> {
+        self.tracks.iter().position(|track| track.slot == Some(slot))
+    }
+
+    pub fn path(&self, track: usize) -> Option<&Path> {
+        self.tracks.get(track).map(|track| track.file.as_path())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn original_soundtrack_keeps_danube_and_reward_out_of_random_play() {
+        let soundtrack = Soundtrack::original(Path::new("music"));
+
+        assert_eq!(soundtrack.tracks_for(MusicContext::Battle, 0xff), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(soundtrack.tracks_for(MusicContext::Hyperspace, 0x3c), vec![2, 3, 4, 5]);
+        assert_eq!(soundtrack.tracks_for(MusicContext::Continuous, 0xff), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(soundtrack.tracks_for(MusicContext::Continuous, 0xc0), Vec::<usize>::new());
+        assert_eq!(soundtrack.tracks_for(MusicContext::Docking, 0), vec![6]);
+        assert_eq!(soundtrack.path(7), Some(Path::new("music/07.ogg")));
+    }
+
+    #[test]
+    fn tracks_without_a_slot_are_always_enabled() {
+        let manifest: Manifest = toml::from_str(r#"
+            [[track]]
+            file = "a.ogg"
+            contexts = ["battle"]
+            slot = 3
+
+            [[track]]
+            file = "b.ogg"
+            contexts = ["battle", "intro"]
+        "#).unwrap();
+
+        let soundtrack = Soundtrack { tracks: manifest.tracks };
+
+        assert_eq!(soundtrack.tracks_for(MusicContext::Battle, 0), vec![1]);
+        assert_eq!(soundtrack.tracks_for(MusicContext::Battle, 0x08), vec![0, 1]);
+        assert_eq!(soundtrack.tracks_for(MusicContext::Intro, 0), vec![1]);
+        assert_eq!(soundtrack.tracks_for(MusicContext::Reward, 0xff), Vec::<usize>::new());
+        assert_eq!(soundtrack.track_in_slot(3), Some(0));
+    }
+
+    #[test]
+    fn game_modes_map_to_contexts() {
+        assert_eq!(MusicContext::from_mode(-3), Some(MusicContext::Hyperspace));
+        assert_eq!(MusicContext::from_mode(6), Some(MusicContext::Docking));
+        assert_eq!(MusicContext::from_mode(4), None);
+    }
+}
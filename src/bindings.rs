@@ -2336,6 +2336,7 @@ pub const GLU_TESS_WINDING_NONZERO: u32 = 100131;
 pub const GLU_TESS_WINDING_POSITIVE: u32 = 100132;
 pub const GLU_TESS_WINDING_NEGATIVE: u32 = 100133;
 pub const GLU_TESS_WINDING_ABS_GEQ_TWO: u32 = 100134;
+pub const HCALL_TRACE_LEN: u32 = 32;
 pub const FALSE: u32 = 0;
 pub const MAC_OS_X_VERSION_10_0: u32 = 1000;
 pub const MAC_OS_X_VERSION_10_1: u32 = 1010;
@@ -8004,6 +8005,31 @@ extern "C" {
 extern "C" {
     pub static mut line_no: ::std::os::raw::c_int;
 }
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct HcallTrace {
+    pub num: ::std::os::raw::c_int,
+    pub line: ::std::os::raw::c_int,
+}
+#[test]
+fn bindgen_test_layout_HcallTrace() {
+    assert_eq!(
+        ::std::mem::size_of::<HcallTrace>(),
+        8usize,
+        concat!("Size of: ", stringify!(HcallTrace))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<HcallTrace>(),
+        4usize,
+        concat!("Alignment of ", stringify!(HcallTrace))
+    );
+}
+extern "C" {
+    pub static mut hcall_trace: [HcallTrace; 32usize];
+}
+extern "C" {
+    pub static mut hcall_trace_pos: ::std::os::raw::c_uint;
+}
 extern "C" {
     pub fn Init680x0() -> ::std::os::raw::c_int;
 }
@@ -8185,6 +8211,13 @@ extern "C" {
 extern "C" {
     pub fn set_main_viewport();
 }
+extern "C" {
+    pub fn draw_osd_string(
+        xpos: ::std::os::raw::c_int,
+        ypos: ::std::os::raw::c_int,
+        str_: *const ::std::os::raw::c_char,
+    );
+}
 extern "C" {
     #[doc = "  \\brief Gets the name of the platform."]
     pub fn SDL_GetPlatform() -> *const ::std::os::raw::c_char;
@@ -25023,6 +25056,12 @@ extern "C" {
 extern "C" {
     pub static mut screen_h: ::std::os::raw::c_int;
 }
+extern "C" {
+    pub static mut screen_x: ::std::os::raw::c_int;
+}
+extern "C" {
+    pub static mut screen_y: ::std::os::raw::c_int;
+}
 extern "C" {
     pub static mut mouse_shown: ::std::os::raw::c_int;
 }
@@ -2,15 +2,18 @@
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
 
-use std::ffi::c_void;
+use std::ffi::{ c_void, CString };
 use std::convert::TryInto;
+use std::ptr::addr_of;
 use ::core::mem;
 
 use sdl2::video::Window;
 
 use crate::audio;
 use crate::screen;
-use crate::GameConfig;
+use crate::osd;
+use crate::savefile;
+use crate::shutdown;
 
 include!("bindings.rs");
 
@@ -57,18 +60,19 @@ pub fn glu_init() {
     }
 }
 
-pub fn init_viewport_gl(config: &GameConfig) {
-    let SCR_TEX_W =	512;
-    let SCR_TEX_H = 256;
-
+/*
+ * The viewports are set from these on every frame. x and y are from the
+ * bottom left of the drawable, the 3d view keeps its aspect ratio and is
+ * scaled with the rest of the screen.
+ */
+pub fn resize_viewport_gl(x: i32, y: i32, w: u32, h: u32) {
     unsafe {
-        screen_h = config.screen_h as i32;
-        screen_w = config.screen_w as i32;
+        screen_x = x;
+        screen_y = y;
+        screen_w = w as i32;
+        screen_h = h as i32;
 
-        glDisable (GL_CULL_FACE);
-        glShadeModel (GL_FLAT);
-        glDisable (GL_DEPTH_TEST);
-        glClearColor (0f32, 0f32, 0f32, 0f32);
+        glViewport (screen_x, screen_y, screen_w, screen_h);
 
         glMatrixMode (GL_PROJECTION);
         glLoadIdentity ();
@@ -76,6 +80,21 @@ pub fn init_viewport_gl(config: &GameConfig) {
         /* aspect ratio of frontier's 3d view is 320/168 = 1.90 */
         gluPerspective (36.5, 1.9, 1.0, 10_000_000_000.0);
 
+        glMatrixMode (GL_MODELVIEW);
+    }
+}
+
+/* the viewport is set up with resize_viewport_gl afterwards */
+pub fn init_viewport_gl() {
+    let SCR_TEX_W =	512;
+    let SCR_TEX_H = 256;
+
+    unsafe {
+        glDisable (GL_CULL_FACE);
+        glShadeModel (GL_FLAT);
+        glDisable (GL_DEPTH_TEST);
+        glClearColor (0f32, 0f32, 0f32, 0f32);
+
         let screen_tex_mut_ptr: *mut u32 = &mut screen_tex;
         let tex_pixels: *const u32 = &0;
         let tex_pixels_cvoid = tex_pixels as *const c_void;
@@ -130,6 +149,49 @@ pub fn c_Call_DumpDebug() {
     }
 }
 
+/* MEM_SIZE in _host.c, the 68k memory isn't bounds checked */
+const STRAM_SIZE: u32 = 0x110000;
+
+/*
+ * Snapshot of the 68k state for crash reports. This doesn't touch any of
+ * the subsystem contexts, so the crash handler can use it at any time.
+ */
+pub struct CpuState {
+    pub line_no: i32,
+    pub regs: [u32; 16],
+    pub stack: Vec<u32>,
+    /* oldest first, as (hostcall number, 68k source line) */
+    pub hostcalls: Vec<(i32, i32)>,
+}
+
+pub fn c_cpu_state() -> CpuState {
+    unsafe {
+        let mut regs = [0; 16];
+
+        for (i, reg) in regs.iter_mut().enumerate() {
+            *reg = GetReg(i as i32) as u32;
+        }
+
+        /* the 68k memory isn't there until Init680x0 has run, and a broken
+         * stack pointer must not take the crash handler down as well */
+        let sp = regs[REG_A7 as usize];
+        let stack = if STRam.is_null() || sp.saturating_add(36) > STRAM_SIZE {
+            vec!()
+        } else {
+            (1..=8).map(|i| MemReadLong(sp + i * 4) as u32).collect()
+        };
+
+        let len = HCALL_TRACE_LEN as usize;
+        let pos = hcall_trace_pos as usize;
+        let hostcalls = (pos.saturating_sub(len)..pos)
+            .map(|i| hcall_trace[i % len])
+            .map(|trace| (trace.num, trace.line))
+            .collect();
+
+        CpuState { line_no, regs, stack, hostcalls }
+    }
+}
+
 pub fn c_get_renderer() -> &'static u32 {
     unsafe {
         &use_renderer
@@ -146,6 +208,17 @@ pub fn c_set_renderer(value: u32) {
     }
 }
 
+pub fn c_draw_osd_string(xpos: i32, ypos: i32, text: &str) {
+    let text = match CString::new(text) {
+        Ok(text) => text,
+        Err(_) => return,
+    };
+
+    unsafe {
+        draw_osd_string(xpos, ypos, text.as_ptr());
+    }
+}
+
 pub fn update_mouse_input(motion_x: isize, motion_y: isize, abs_x: usize, abs_y: usize) {
     unsafe {
         input.motion_x += motion_x as i32;
@@ -155,7 +228,7 @@ pub fn update_mouse_input(motion_x: isize, motion_y: isize, abs_x: usize, abs_y:
     }
 }
 
-pub fn unsafe_nu_draw_screen(window: &Window) {
+pub fn build_palettes() {
     /* build RGB palettes */
     let (main_rgb_palette, main_palette, main_palette_len) = unsafe {
         (&mut MainRGBPalette, &MainPalette, len_main_palette as usize)
@@ -167,11 +240,38 @@ pub fn unsafe_nu_draw_screen(window: &Window) {
 
     screen::build_rgb_palette(main_rgb_palette, main_palette, main_palette_len);
     screen::build_rgb_palette(ctrl_rgb_palette, ctrl_palette, 16);
+}
+
+pub fn unsafe_copy_video_raster(pixels: &mut [u32], width: usize, height: usize) {
+    unsafe {
+        if VideoRaster.is_null() {
+            return;
+        }
+
+        let raster = std::slice::from_raw_parts(VideoRaster, width * height);
+
+        for y in 0..height {
+            /* the control panel at the bottom has its own palette */
+            let palette: &[u32] = if y >= 168 { &*addr_of!(CtrlRGBPalette) } else { &*addr_of!(MainRGBPalette) };
+
+            for x in 0..width {
+                let index = raster[y * width + x] as usize;
+
+                pixels[y * width + x] = palette.get(index).copied().unwrap_or(0);
+            }
+        }
+    }
+}
+
+pub fn unsafe_nu_draw_screen(window: &Window) {
+    build_palettes();
 
     unsafe {
         //fprintf (stderr, "Render: ");
         if !znode_cur.is_null() {
             end_node();
+            /* the list is complete now, so drawing it again (while paused) doesn't append anything */
+            znode_cur = std::ptr::null_mut();
         }
 
         //printf ("Frame: %d znodes.\n", znode_buf_pos);
@@ -186,21 +286,31 @@ pub fn unsafe_nu_draw_screen(window: &Window) {
         // }
 
         draw_control_panel();
+    }
+
+    osd::draw();
+
+    unsafe {
         glFlush();
     }
 
     window.gl_swap_window();
 
-    /* frontier background color... */
     unsafe {
-        if use_renderer == RENDERERS_R_GLWIRE {
-            glClearColor (0.0, 0.0, 0.0, 0.0);
-        } else {
+        /* black outside of the screen when it is letterboxed */
+        glClearColor (0.0, 0.0, 0.0, 0.0);
+        glClear(GL_COLOR_BUFFER_BIT);
+
+        /* frontier background color... */
+        if use_renderer != RENDERERS_R_GLWIRE {
             set_gl_clear_col(MainRGBPalette[fe2_bgcol as usize] as i32);
         }
 
+        glEnable(GL_SCISSOR_TEST);
+        glScissor(screen_x, screen_y, screen_w, screen_h);
         glMatrixMode(GL_MODELVIEW);
         glClear(GL_COLOR_BUFFER_BIT | GL_DEPTH_BUFFER_BIT);
+        glDisable(GL_SCISSOR_TEST);
         glLoadIdentity();
         set_main_viewport();
     }
@@ -210,12 +320,14 @@ pub fn unsafe_nu_draw_screen(window: &Window) {
 
 #[no_mangle]
 extern "C" fn Main_EventHandler() {
-    crate::event_handler()
+    crate::event_handler();
+    shutdown::check();
 }
 
 #[no_mangle]
 extern "C" fn Call_Idle() {
-	crate::idle()
+	crate::idle();
+    shutdown::check();
 }
 
 // Audio module C interface
@@ -226,19 +338,36 @@ extern "C" fn Call_PlaySFX() {
     	(GetReg(REG_D0 as i32), GetReg(REG_D1 as i32))
     };
 
-    audio::play_sfx(sample.try_into().unwrap(), channel.try_into().unwrap())
+    match (sample.try_into(), channel.try_into()) {
+        (Ok(sample), Ok(channel)) => audio::play_sfx(sample, channel),
+        _ => warn!(target: "hostcall", "invalid sfx {} on channel {}", sample, channel),
+    }
+}
+
+/* sfx in d0, d2 is the pan from -256 (left) to 256 (right), d3 the distance; the voice is picked on our side */
+#[no_mangle]
+extern "C" fn Call_PlaySFXEx() {
+    let (sample, pan, distance) = unsafe {
+    	(GetReg(REG_D0 as i32), GetReg(REG_D2 as i32) as i16, GetReg(REG_D3 as i32))
+    };
+
+    match (sample.try_into(), distance.try_into()) {
+        (Ok(sample), Ok(distance)) => audio::play_sfx_at(sample, pan as f32 / 256.0, distance),
+        _ => warn!(target: "hostcall", "invalid sfx {} at distance {}", sample, distance),
+    }
 }
 
 #[no_mangle]
 extern "C" fn Call_PlayMusic() {
-    println!("entered play music handler!");
+    trace!(target: "hostcall", "entered play music handler");
 
     let (reg0, reg1, reg2) = unsafe {
         (GetReg(0) as i32, GetReg(1) as i32, GetReg(2) as i32)
     };
 
     /* Playing mode in d0:
-     * -2 = play random track once
+     * -3 = play random hyperspace track once
+     * -2 = play random battle track once
      * -1 = play random tracks continuously
      * 0+ = play specific track once
      * d1:d2 is a mask of enabled tracks
@@ -281,16 +410,16 @@ extern "C" fn Call_PlayMusic() {
 
     audio::play_music(music_mode, enabled_tracks);
 
-    println!("left play music handler!");
+    trace!(target: "hostcall", "left play music handler");
 }
 
 #[no_mangle]
 extern "C" fn Call_StopMusic() {
-    println!("entered stop music handler!");
+    trace!(target: "hostcall", "entered stop music handler");
 
     audio::stop_music();
 
-    println!("left stop music handler!");
+    trace!(target: "hostcall", "left stop music handler");
 }
 
 #[no_mangle]
@@ -319,6 +448,7 @@ extern "C" fn Call_IsMusicPlaying() {
 #[no_mangle]
 extern "C" fn Nu_DrawScreen() {
     screen::nu_draw_screen();
+    shutdown::check();
 }
 
 // Keymap module C interface
@@ -332,3 +462,117 @@ extern "C" fn Keymap_DebounceAllKeys() {
 extern "C" fn ShortCut_CheckKeys() {
     // empty dummy function
 }
+
+// Savefile module C interface
+
+/* a zero terminated string in 68k memory */
+fn read_st_string(mut addr: u32) -> String {
+    let mut bytes = vec!();
+
+    while addr < STRAM_SIZE {
+        let byte = unsafe { MemReadByte(addr) } as u8;
+
+        if byte == 0 {
+            break;
+        }
+
+        bytes.push(byte);
+        addr += 1;
+    }
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+fn st_ram_slice(addr: u32, len: u32) -> Option<&'static mut [u8]> {
+    if !matches!(addr.checked_add(len), Some(end) if end <= STRAM_SIZE) {
+        warn!(target: "hostcall", "buffer ${:x} with length {} is outside of the 68k memory", addr, len);
+        return None;
+    }
+
+    unsafe {
+        Some(std::slice::from_raw_parts_mut(STRam.add(addr as usize) as *mut u8, len as usize))
+    }
+}
+
+#[no_mangle]
+extern "C" fn Call_Fread() {
+    let (filename, buffer, len) = unsafe {
+        (GetReg(REG_D1 as i32) as u32, GetReg(REG_A4 as i32) as u32, GetReg(REG_D7 as i32) as u32)
+    };
+
+    let count = match st_ram_slice(buffer, len) {
+        Some(buffer) => savefile::read(&read_st_string(filename), buffer),
+        None => 0,
+    };
+
+    unsafe {
+        SetReg(REG_D0 as i32, count as i32);
+    }
+}
+
+#[no_mangle]
+extern "C" fn Call_Fwrite() {
+    let (filename, buffer, len) = unsafe {
+        (GetReg(REG_D1 as i32) as u32, GetReg(REG_A4 as i32) as u32, GetReg(REG_D7 as i32) as u32)
+    };
+
+    let count = match st_ram_slice(buffer, len) {
+        Some(buffer) => savefile::write(&read_st_string(filename), buffer),
+        None => 0,
+    };
+
+    unsafe {
+        SetReg(REG_D0 as i32, count as i32);
+    }
+}
+
+#[no_mangle]
+extern "C" fn Call_Fdelete() {
+    let filename = unsafe { GetReg(REG_D1 as i32) as u32 };
+    let deleted = savefile::delete(&read_st_string(filename));
+
+    unsafe {
+        SetReg(REG_D0 as i32, if deleted { 0 } else { -1 });
+    }
+}
+
+#[no_mangle]
+extern "C" fn Call_Fopendir() {
+    let dirname = unsafe { GetReg(REG_A2 as i32) as u32 };
+    let opened = savefile::open_dir(&read_st_string(dirname));
+
+    unsafe {
+        SetReg(REG_D0 as i32, if opened { 0 } else { -1 });
+    }
+}
+
+/* filename into buffer (a0), attributes d2, len d1 */
+#[no_mangle]
+extern "C" fn Call_Freaddir() {
+    let entry = match savefile::read_dir() {
+        Some(entry) => entry,
+        None => {
+            unsafe {
+                SetReg(REG_D0 as i32, -1);
+            }
+            return;
+        }
+    };
+
+    unsafe {
+        let buffer = GetReg(REG_A0 as i32) as u32;
+
+        for (i, byte) in entry.name.iter().enumerate() {
+            MemWriteByte(buffer + i as u32, *byte as i32);
+        }
+
+        SetReg(REG_D2 as i32, entry.attribs);
+        SetReg(REG_D1 as i32, entry.len);
+        SetReg(REG_D0 as i32, 0);
+    }
+}
+
+#[no_mangle]
+extern "C" fn Call_Fclosedir() {
+    savefile::close_dir();
+}
@@ -0,0 +1,269 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use clap::{ App, AppSettings, Arg, ArgMatches, ErrorKind, SubCommand };
+
+use crate::GameConfig;
+use crate::config::ScaleMode;
+use crate::logging::LogFilter;
+
+pub enum Command {
+    Run,
+    InspectSave(PathBuf),
+    ListAudioDevices,
+    DumpConfig,
+}
+
+pub struct Cli {
+    pub command: Command,
+    matches: ArgMatches<'static>,
+}
+
+/* the ST screen, anything smaller can't show it */
+static MIN_WINDOW_SIZE: (u32, u32) = (320, 200);
+
+fn is_number(value: String) -> Result<(), String> {
+    value.parse::<u32>().map(|_| ()).map_err(|_| format!("\"{}\" is not a number", value))
+}
+
+fn is_frequency(value: String) -> Result<(), String> {
+    match value.parse::<i32>() {
+        Ok(freq) if freq >= 0 => Ok(()),
+        _ => Err(format!("\"{}\" is not a frequency in Hz", value)),
+    }
+}
+
+fn is_buffer_size(value: String) -> Result<(), String> {
+    match value.parse::<u16>() {
+        Ok(samples) if samples >= 64 => Ok(()),
+        _ => Err(format!("\"{}\" is not a buffer size between 64 and 65535", value)),
+    }
+}
+
+fn is_volume(value: String) -> Result<(), String> {
+    match value.parse::<u8>() {
+        Ok(percent) if percent <= 100 => Ok(()),
+        _ => Err(format!("\"{}\" is not a volume between 0 and 100", value)),
+    }
+}
+
+fn is_log_filter(value: String) -> Result<(), String> {
+    LogFilter::from_str(&value).map(|_| ())
+}
+
+/* clap validates w and h one at a time, this gets both */
+fn is_window_size(size: &[&str]) -> Result<(), String> {
+    let (min_w, min_h) = MIN_WINDOW_SIZE;
+
+    match size.iter().map(|value| value.parse::<u32>()).collect::<Result<Vec<_>, _>>() {
+        Ok(size) if size.len() == 2 && size[0] >= min_w && size[1] >= min_h => Ok(()),
+        _ => Err(format!("Invalid value for '--size': \"{}\" is not a window size of at least {} {}", size.join(" "), min_w, min_h)),
+    }
+}
+
+/* everything that changes how the game runs, also accepted without "run" */
+fn run_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("fullscreen").long("fullscreen").short("f")
+            .help("Try to use fullscreen mode"),
+        Arg::with_name("windowed").long("windowed").conflicts_with("fullscreen")
+            .help("Run in a window, even if the config says fullscreen"),
+        Arg::with_name("nosound").long("nosound")
+            .help("Disable sound (faster!)"),
+        Arg::with_name("sound").long("sound").conflicts_with("nosound")
+            .help("Enable sound, even if the config disables it"),
+        Arg::with_name("audio-device").long("audio-device").value_name("name")
+            .help("Play on this device, see list-audio-devices"),
+        Arg::with_name("audio-frequency").long("audio-frequency").value_name("hz").validator(is_frequency)
+            .help("Ask the audio device for this rate, 0 for SDL's default"),
+        Arg::with_name("audio-buffer").long("audio-buffer").value_name("samples").validator(is_buffer_size)
+            .help("Audio buffer size, smaller means less latency"),
+        Arg::with_name("audio-out").long("audio-out").value_name("file")
+            .help("Write the sound to a WAV file instead of playing it"),
+        Arg::with_name("sound-pack").long("sound-pack").value_name("dir")
+            .help("Replace sound effects with the ones in dir"),
+        Arg::with_name("volume").long("volume").value_name("percent").validator(is_volume)
+            .help("Master volume"),
+        Arg::with_name("music-volume").long("music-volume").value_name("percent").validator(is_volume)
+            .help("Music volume"),
+        Arg::with_name("sfx-volume").long("sfx-volume").value_name("percent").validator(is_volume)
+            .help("Sound effects volume"),
+        Arg::with_name("size").long("size").value_names(&["w", "h"])
+            .help("Start at the specified window size, at least 320 200"),
+        Arg::with_name("scale").long("scale").value_name("mode")
+            .possible_values(&["stretch", "letterbox", "integer"])
+            .help("Fit the screen to the window"),
+        Arg::with_name("no-autopause").long("no-autopause")
+            .help("Keep running when the window loses focus"),
+        Arg::with_name("vbl-timer").long("vbl-timer")
+            .help("Raise the VBL from an SDL timer"),
+        Arg::with_name("headless").long("headless")
+            .help("Run without a window, sound device or input"),
+        Arg::with_name("frames").long("frames").value_name("n").validator(is_number).requires("headless")
+            .help("Quit after n frames"),
+        Arg::with_name("frame-dump").long("frame-dump").value_name("file").requires("frames")
+            .help("Write the last frame as PPM, needs --frames"),
+    ]
+}
+
+fn app() -> App<'static, 'static> {
+    App::new("frontier")
+        .about("Frontier: Elite II, running on a 68k to C translation")
+        .version(env!("CARGO_PKG_VERSION"))
+        .setting(AppSettings::VersionlessSubcommands)
+        .arg(Arg::with_name("config").long("config").value_name("file").global(true)
+            .help("Use the given config file"))
+        .arg(Arg::with_name("data-dir").long("data-dir").value_name("dir").global(true)
+            .help("Load sfx and music from dir"))
+        .arg(Arg::with_name("log").long("log").value_name("filter").validator(is_log_filter).global(true)
+            .help("Log filter, e.g. \"warn,audio=debug\""))
+        .args(&run_args())
+        .subcommand(SubCommand::with_name("run")
+            .about("Run the game (the default)")
+            .args(&run_args()))
+        .subcommand(SubCommand::with_name("inspect-save")
+            .about("Decode a savefile and print what is in it")
+            .arg(Arg::with_name("file").required(true)))
+        .subcommand(SubCommand::with_name("list-audio-devices")
+            .about("List the audio outputs SDL knows about"))
+        .subcommand(SubCommand::with_name("dump-config")
+            .about("Print the configuration with all options applied")
+            .args(&run_args()))
+}
+
+/* exits with a usage message if the arguments don't make sense */
+pub fn parse() -> Cli {
+    let matches = app().get_matches();
+
+    let command = match matches.subcommand() {
+        ("inspect-save", Some(sub_matches)) => {
+            Command::InspectSave(PathBuf::from(sub_matches.value_of("file").unwrap_or_default()))
+        },
+        ("list-audio-devices", _) => Command::ListAudioDevices,
+        ("dump-config", _) => Command::DumpConfig,
+        _ => Command::Run,
+    };
+
+    let cli = Cli { command, matches };
+
+    if let Some(size) = cli.command_matches().values_of("size") {
+        if let Err(message) = is_window_size(&size.collect::<Vec<_>>()) {
+            clap::Error::with_description(&message, ErrorKind::ValueValidation).exit();
+        }
+    }
+
+    cli
+}
+
+impl Cli {
+    /* the options of the subcommand, or the top level ones without one */
+    fn command_matches(&self) -> &ArgMatches<'static> {
+        match self.matches.subcommand() {
+            (_, Some(sub_matches)) => sub_matches,
+            _ => &self.matches,
+        }
+    }
+
+    pub fn log_filter(&self) -> LogFilter {
+        self.command_matches().value_of("log")
+            .and_then(|filter| filter.parse().ok())
+            .unwrap_or_default()
+    }
+
+    pub fn config_path(&self) -> Option<PathBuf> {
+        self.command_matches().value_of("config").map(PathBuf::from)
+    }
+
+    /* passed parameters take precedence over the config file */
+    pub fn apply(&self, mut config: GameConfig) -> GameConfig {
+        let matches = self.command_matches();
+
+        if matches.is_present("fullscreen") {
+            config.use_fullscreen = true;
+        }
+
+        if matches.is_present("windowed") {
+            config.use_fullscreen = false;
+        }
+
+        if matches.is_present("nosound") {
+            config.nosound = true;
+        }
+
+        if matches.is_present("sound") {
+            config.nosound = false;
+        }
+
+        if let Some(name) = matches.value_of("audio-device") {
+            config.audio_device = Some(name.to_string());
+        }
+
+        if let Some(freq) = matches.value_of("audio-frequency") {
+            config.audio_frequency = freq.parse().unwrap_or(config.audio_frequency);
+        }
+
+        if let Some(samples) = matches.value_of("audio-buffer") {
+            config.audio_buffer = samples.parse().unwrap_or(config.audio_buffer);
+        }
+
+        if let Some(path) = matches.value_of("audio-out") {
+            config.audio_out = Some(PathBuf::from(path));
+        }
+
+        if let Some(dir) = matches.value_of("sound-pack") {
+            config.sound_pack = Some(PathBuf::from(dir));
+        }
+
+        if let Some(percent) = matches.value_of("volume") {
+            config.master_volume = percent.parse().unwrap_or(config.master_volume);
+        }
+
+        if let Some(percent) = matches.value_of("music-volume") {
+            config.music_volume = percent.parse().unwrap_or(config.music_volume);
+        }
+
+        if let Some(percent) = matches.value_of("sfx-volume") {
+            config.sfx_volume = percent.parse().unwrap_or(config.sfx_volume);
+        }
+
+        if let Some(mut size) = matches.values_of("size") {
+            /* both are there and big enough, parse checked that */
+            config.screen_w = size.next().and_then(|w| w.parse().ok()).unwrap_or(config.screen_w);
+            config.screen_h = size.next().and_then(|h| h.parse().ok()).unwrap_or(config.screen_h);
+        }
+
+        if let Some(mode) = matches.value_of("scale") {
+            config.scale_mode = match mode {
+                "letterbox" => ScaleMode::Letterbox,
+                "integer" => ScaleMode::Integer,
+                _ => ScaleMode::Stretch,
+            };
+        }
+
+        if matches.is_present("no-autopause") {
+            config.pause_on_focus_loss = false;
+        }
+
+        if matches.is_present("vbl-timer") {
+            config.vbl_timer = true;
+        }
+
+        if matches.is_present("headless") {
+            config.headless = true;
+        }
+
+        if let Some(frames) = matches.value_of("frames") {
+            config.headless_frames = frames.parse().ok();
+        }
+
+        if let Some(path) = matches.value_of("frame-dump") {
+            config.frame_dump = Some(PathBuf::from(path));
+        }
+
+        if let Some(dir) = matches.value_of("data-dir") {
+            config.data_dir = Some(PathBuf::from(dir));
+        }
+
+        config
+    }
+}
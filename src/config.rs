@@ -0,0 +1,245 @@
+use std::fs;
+use std::io;
+use std::cell::RefCell;
+use std::path::{ Path, PathBuf };
+
+use serde::{ Serialize, Deserialize };
+
+use crate::c_lib::{
+    RENDERERS, RENDERERS_R_OLD, RENDERERS_R_GLWIRE, RENDERERS_R_GL
+};
+
+static CONFIG_DIR_NAME: &str = "final-frontier";
+static CONFIG_FILE_NAME: &str = "config.toml";
+
+thread_local! {
+    static CONFIG_STORE: RefCell<Option<ConfigStore>> = RefCell::default();
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Renderer {
+    Old,
+    GlWire,
+    Gl,
+}
+
+impl Renderer {
+    pub fn from_c(value: RENDERERS) -> Self {
+        match value {
+            RENDERERS_R_OLD => Renderer::Old,
+            RENDERERS_R_GLWIRE => Renderer::GlWire,
+            _ => Renderer::Gl,
+        }
+    }
+
+    pub fn to_c(self) -> RENDERERS {
+        match self {
+            Renderer::Old => RENDERERS_R_OLD,
+            Renderer::GlWire => RENDERERS_R_GLWIRE,
+            Renderer::Gl => RENDERERS_R_GL,
+        }
+    }
+}
+
+/* how the 320x200 screen is fitted into the window */
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScaleMode {
+    /* over the whole window, whatever its shape */
+    Stretch,
+    /* the largest 4:3 area, like on the monitor of an ST */
+    Letterbox,
+    /* the largest whole multiple of 320x200, every pixel the same size */
+    Integer,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameConfig {
+    pub use_fullscreen: bool,
+    pub nosound: bool,
+    /* a name from list-audio-devices, SDL's default device if not set */
+    pub audio_device: Option<String>,
+    /* mixed at and asked of SDL, 0 takes its default */
+    pub audio_frequency: i32,
+    /* samples per channel, the latency is audio_buffer / audio_frequency */
+    pub audio_buffer: u16,
+    /* in percent, the music and sfx levels are scaled by the master one */
+    pub master_volume: u8,
+    pub music_volume: u8,
+    pub sfx_volume: u8,
+    /* 0 cuts the music off like the original did */
+    pub music_fade_out_ms: u32,
+    /* from the hyperspace to the battle music, other switches cut */
+    pub music_crossfade_ms: u32,
+    pub screen_w: u32,
+    pub screen_h: u32,
+    pub scale_mode: ScaleMode,
+    pub renderer: Renderer,
+    pub grab_mouse: bool,
+    pub vbl_timer: bool,
+    pub pause_on_focus_loss: bool,
+    /* where sfx/ and music/ live, searched for if not set */
+    pub data_dir: Option<PathBuf>,
+    pub save_dir: Option<PathBuf>,
+    /* music/music.toml in the data dir if not set */
+    pub music_manifest: Option<PathBuf>,
+    /* replacement sfx, with sounds.toml for loops and gains */
+    pub sound_pack: Option<PathBuf>,
+
+    /* runtime only options, never read from or written to the file */
+    #[serde(skip)]
+    pub headless: bool,
+    #[serde(skip)]
+    pub headless_frames: Option<u32>,
+    #[serde(skip)]
+    pub frame_dump: Option<PathBuf>,
+    /* mix into this wav file instead of the sound device */
+    #[serde(skip)]
+    pub audio_out: Option<PathBuf>,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            use_fullscreen: false,
+            nosound: false,
+            audio_device: None,
+            audio_frequency: 0,
+            audio_buffer: 1024,
+            master_volume: 100,
+            music_volume: 100,
+            sfx_volume: 100,
+            music_fade_out_ms: 1000,
+            music_crossfade_ms: 2000,
+            screen_w: 640,
+            screen_h: 480,
+            scale_mode: ScaleMode::Stretch,
+            renderer: Renderer::Gl,
+            grab_mouse: false,
+            vbl_timer: false,
+            pause_on_focus_loss: true,
+            data_dir: None,
+            save_dir: None,
+            music_manifest: None,
+            sound_pack: None,
+            headless: false,
+            headless_frames: None,
+            frame_dump: None,
+            audio_out: None,
+        }
+    }
+}
+
+/*
+ * Keeps the config as it was read from disk, so options that only have been
+ * passed on the command line don't end up in the file. Only settings that
+ * are changed while the game is running get merged into it.
+ */
+struct ConfigStore {
+    path: PathBuf,
+    file_config: GameConfig,
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(CONFIG_DIR_NAME).join(CONFIG_FILE_NAME))
+}
+
+/* None if there is a file but it can't be used, it mustn't be overwritten then */
+fn read_file(path: &Path) -> Option<GameConfig> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            info!("no config file at {}, using defaults", path.display());
+            return Some(GameConfig::default());
+        },
+        Err(error) => {
+            error!("unable to read {}: {}", path.display(), error);
+            return None;
+        }
+    };
+
+    match toml::from_str(&content) {
+        Ok(config) => Some(config),
+        Err(error) => {
+            error!("unable to parse {}: {}", path.display(), error);
+            None
+        }
+    }
+}
+
+/*
+ * Loads the config file either from the given path or from the default
+ * location in the XDG config dir. Returns the config which can then be
+ * overriden by the command line parameters.
+ */
+pub fn load(path: Option<PathBuf>) -> GameConfig {
+    let path = match path.or_else(default_path) {
+        Some(path) => path,
+        None => {
+            warn!("no config directory available, using defaults");
+            return GameConfig::default();
+        }
+    };
+
+    let file_config = match read_file(&path) {
+        Some(file_config) => file_config,
+        None => {
+            warn!("using defaults, changes won't be saved to {}", path.display());
+            return GameConfig::default();
+        }
+    };
+    let config = file_config.clone();
+
+    CONFIG_STORE.with(|ref_cell| {
+        *ref_cell.borrow_mut() = Some(ConfigStore { path, file_config });
+    });
+
+    config
+}
+
+/*
+ * Records a setting that has been changed at runtime, so it is persisted
+ * the next time the config gets saved.
+ */
+pub fn update<F: FnOnce(&mut GameConfig)>(change: F) {
+    with_static_ref_option!([CONFIG_STORE => store] {
+        change(&mut store.file_config);
+    } or {});
+}
+
+pub fn to_toml(config: &GameConfig) -> String {
+    toml::to_string_pretty(config).unwrap_or_else(|error| {
+        error!("unable to serialize config: {}", error);
+        String::new()
+    })
+}
+
+pub fn save() {
+    with_static_ref_option!([CONFIG_STORE => store] {
+        if let Some(dir) = store.path.parent() {
+            if let Err(error) = fs::create_dir_all(dir) {
+                error!("unable to create {}: {}", dir.display(), error);
+                return;
+            }
+        }
+
+        let content = match toml::to_string_pretty(&store.file_config) {
+            Ok(content) => content,
+            Err(error) => {
+                error!("unable to serialize config: {}", error);
+                return;
+            }
+        };
+
+        if let Err(error) = fs::write(&store.path, content) {
+            error!("unable to write {}: {}", store.path.display(), error);
+            return;
+        }
+
+        info!("saved to {}", store.path.display());
+    } or {
+        debug!("nothing to save");
+    });
+}
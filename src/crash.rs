@@ -0,0 +1,165 @@
+use std::env;
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::io;
+use std::mem;
+use std::panic;
+use std::path::PathBuf;
+use std::ptr;
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::thread;
+use std::time::{ SystemTime, UNIX_EPOCH };
+
+use libc::c_int;
+
+use crate::c_lib;
+use crate::logging;
+
+static CRASH_SIGNALS: [c_int; 5] = [
+    libc::SIGSEGV, libc::SIGBUS, libc::SIGILL, libc::SIGFPE, libc::SIGABRT,
+];
+
+/* a crash while writing the report must not end up in here again */
+static CRASHING: AtomicBool = AtomicBool::new(false);
+
+/*
+ * Replaces the sig_handler of lib_main.c. Faults in the generated 68k code
+ * and Rust panics both end up in a crash report. Has to be called on the
+ * thread that runs the emulation.
+ */
+pub fn install() {
+    let default_hook = panic::take_hook();
+    let emulation_thread = thread::current().id();
+
+    panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+
+        /* other threads own no 68k state and are recovered from, like the music thread */
+        if thread::current().id() == emulation_thread {
+            report(&format!("panic: {}", info));
+        }
+    }));
+
+    for &signum in CRASH_SIGNALS.iter() {
+        unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+
+            action.sa_sigaction = signal_handler as extern "C" fn(c_int) as usize;
+            /* the default action is back once we return, see signal_handler */
+            action.sa_flags = libc::SA_RESETHAND;
+            libc::sigemptyset(&mut action.sa_mask);
+
+            if libc::sigaction(signum, &action, ptr::null_mut()) != 0 {
+                warn!("could not install handler for signal {}", signum);
+            }
+        }
+    }
+}
+
+extern "C" fn signal_handler(signum: c_int) {
+    report(&format!("signal {} ({})", signum, signal_name(signum)));
+
+    /* terminate the way we would have without the handler, core dump included */
+    unsafe {
+        libc::raise(signum);
+    }
+}
+
+fn signal_name(signum: c_int) -> &'static str {
+    match signum {
+        libc::SIGSEGV => "SIGSEGV",
+        libc::SIGBUS => "SIGBUS",
+        libc::SIGILL => "SIGILL",
+        libc::SIGFPE => "SIGFPE",
+        libc::SIGABRT => "SIGABRT",
+        _ => "unknown",
+    }
+}
+
+/*
+ * We are about to die anyway, so this doesn't bother being async signal
+ * safe. The report is written before Call_DumpDebug runs, as that reads
+ * the 68k stack without any checks.
+ */
+fn report(reason: &str) {
+    if CRASHING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    eprintln!("Crash: {}", reason);
+
+    let text = format_report(reason, &c_lib::c_cpu_state());
+
+    match write_report(&text) {
+        Ok(path) => eprintln!("Crash: report written to {}", path.display()),
+        Err(error) => {
+            eprintln!("Crash: could not write report: {}", error);
+            eprint!("{}", text);
+        },
+    }
+
+    c_lib::c_Call_DumpDebug();
+}
+
+fn format_report(reason: &str, state: &c_lib::CpuState) -> String {
+    let mut text = String::new();
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+
+    /* writing into a String can't fail */
+    let _ = writeln!(text, "Final Frontier crash report");
+    let _ = writeln!(text, "version: {}", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(text, "time: {}", timestamp);
+    let _ = writeln!(text, "reason: {}", reason);
+    let _ = writeln!(text);
+    let _ = writeln!(text, "PC @ 68k line {}", state.line_no);
+
+    let _ = write!(text, "D:");
+    for reg in &state.regs[0..8] {
+        let _ = write!(text, " ${:08x}", reg);
+    }
+    let _ = writeln!(text);
+
+    let _ = write!(text, "A:");
+    for reg in &state.regs[8..16] {
+        let _ = write!(text, " ${:08x}", reg);
+    }
+    let _ = writeln!(text);
+
+    let _ = write!(text, "Stack:");
+    for value in &state.stack {
+        let _ = write!(text, " ${:x}", value);
+    }
+    let _ = writeln!(text);
+    let _ = writeln!(text);
+
+    let _ = writeln!(text, "Last hostcalls, oldest first:");
+    for (num, line) in &state.hostcalls {
+        let _ = writeln!(text, "  ${:02x} @ 68k line {}", num, line);
+    }
+    let _ = writeln!(text);
+
+    let _ = writeln!(text, "Recent log messages:");
+    for line in logging::recent() {
+        let _ = writeln!(text, "  {}", line);
+    }
+
+    text
+}
+
+fn report_dir() -> PathBuf {
+    dirs::data_local_dir()
+        .unwrap_or_else(env::temp_dir)
+        .join("final-frontier")
+        .join("crashes")
+}
+
+fn write_report(text: &str) -> io::Result<PathBuf> {
+    let dir = report_dir();
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+    let path = dir.join(format!("crash-{}.txt", timestamp));
+
+    fs::create_dir_all(&dir)?;
+    fs::write(&path, text)?;
+
+    Ok(path)
+}
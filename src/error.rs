@@ -0,0 +1,62 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use lewton::VorbisError;
+use sdl2::video::WindowBuildError;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    /* a required SDL subsystem could not be initialized */
+    Sdl(String),
+    Video(String),
+    Audio(String),
+    Music(PathBuf, String),
+    Savefile(PathBuf, String),
+    Io(io::Error),
+    Vorbis(VorbisError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Sdl(message) => write!(f, "SDL: {}", message),
+            Error::Video(message) => write!(f, "Video: {}", message),
+            Error::Audio(message) => write!(f, "Sound: {}", message),
+            Error::Music(path, message) => write!(f, "Music: {}: {}", path.display(), message),
+            Error::Savefile(path, message) => write!(f, "Savefile: {}: {}", path.display(), message),
+            Error::Io(error) => write!(f, "I/O: {}", error),
+            Error::Vorbis(error) => write!(f, "Vorbis: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            Error::Vorbis(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<VorbisError> for Error {
+    fn from(error: VorbisError) -> Self {
+        Error::Vorbis(error)
+    }
+}
+
+impl From<WindowBuildError> for Error {
+    fn from(error: WindowBuildError) -> Self {
+        Error::Video(error.to_string())
+    }
+}
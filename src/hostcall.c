@@ -728,134 +728,6 @@ void DumpRegsChanged ()
 	printf (" @%d\n", line_no);
 }
 
-static void Call_Fdelete ()
-{
-	int p, i;
-	char filename[64];
-
-	p = GetReg (REG_D1);
-	for (i=0; ; i++) {
-		filename[i] = STMemory_ReadByte (p++);
-		if (!filename[i]) break;
-	}
-
-	SetReg (REG_D0, remove (filename));
-}
-
-static void Call_Fwrite ()
-{
-	int p, i;
-	int pBuf = GetReg (REG_A4);
-	int len = GetReg (REG_D7);
-	char filename[64];
-	FILE *f;
-
-	p = GetReg (REG_D1);
-	for (i=0; ; i++) {
-		filename[i] = STMemory_ReadByte (p++);
-		if (!filename[i]) break;
-	}
-
-	if (!(f = fopen (filename, "wb"))) {
-		SetReg (REG_D0, 0);
-	} else {
-		SetReg (REG_D0, fwrite (STRam+pBuf, 1, len, f));
-		fclose (f);
-	}
-}
-
-static void Call_Fread ()
-{
-	int p, i;
-	int pBuf = GetReg (REG_A4);
-	int len = GetReg (REG_D7);
-	char filename[64];
-	FILE *f;
-
-	p = GetReg (REG_D1);
-	for (i=0; ; i++) {
-		filename[i] = STMemory_ReadByte (p++);
-		if (!filename[i]) break;
-	}
-
-	if (!(f = fopen (filename, "rb"))) {
-		SetReg (REG_D0, 0);
-	} else {
-		SetReg (REG_D0, fread (STRam+pBuf, 1, len, f));
-		fclose (f);
-	}
-}
-
-#include <dirent.h>
-static DIR *poodir;
-
-static char cur_dir[1024];
-
-static void Call_Fopendir ()
-{
-	int p, i;
-	char name[64];
-
-	p = GetReg (REG_A2);
-	for (i=0; ; i++) {
-		name[i] = STMemory_ReadByte (p++);
-		if (!name[i]) break;
-	}
-
-	strncpy (cur_dir, name, 1024);
-
-	poodir = opendir (name);
-	if (poodir) {
-		struct dirent *dent;
-		/* skip '.' and '..' */
-		dent = readdir (poodir);
-		dent = readdir (poodir);
-		SetReg (REG_D0, 0);
-	} else {
-		SetReg (REG_D0, -1);
-	}
-}
-
-static void Call_Fclosedir ()
-{
-	closedir (poodir);
-}
-
-/* make sure fe2.s is allocating enough space at a0... */
-#define MAX_FILENAME_LEN	14
-
-static void Call_Freaddir ()
-{
-	int p, i, attribs, len;
-	char name[MAX_FILENAME_LEN];
-	/* filename into buffer (a0), attributes d2, len d1 */
-	char full_path_shit[1024];
-	struct stat _stat;
-	struct dirent *dent = readdir (poodir);
-	if (dent == NULL) {
-		SetReg (REG_D0, -1);
-		return;
-	}
-	strncpy (name, dent->d_name, MAX_FILENAME_LEN);
-	name[MAX_FILENAME_LEN-1] = '\0';
-
-	strncpy (full_path_shit, cur_dir, 1024);
-	strncat (full_path_shit, "/", 1024);
-	strncat (full_path_shit, dent->d_name, 1024);
-	stat (full_path_shit, &_stat);
-
-	len = _stat.st_size;
-	attribs = (S_ISDIR (_stat.st_mode) ? 0x10 : 0);
-
-	p = GetReg (REG_A0);
-	for (i=0; i<MAX_FILENAME_LEN; i++) {
-		STMemory_WriteByte (p++, name[i]);
-	}
-	SetReg (REG_D2, attribs);
-	SetReg (REG_D1, len);
-	SetReg (REG_D0, 0);
-}
-
 HOSTCALL hcalls [] = {
 	&SetExceptionHandler,
 	&Call_Memset,		/* 0x1 */
@@ -897,7 +769,7 @@ HOSTCALL hcalls [] = {
 	&Call_Fopendir,			/* 0x25 */
 	&Call_Freaddir,
 	&Call_Fclosedir,
-	NULL,
+	&Call_PlaySFXEx,		/* 0x28 */
 	NULL,
 	NULL,
 	NULL,
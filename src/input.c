@@ -35,8 +35,8 @@ void Call_GetMouseInput ()
 	mouse_mov[0] = SDL_SwapBE16 (SDL_SwapBE16 (mouse_mov[0]) + input.motion_x);
 	mouse_mov[1] = SDL_SwapBE16 (SDL_SwapBE16 (mouse_mov[1]) + input.motion_y);
 
-	mouse_abs[0] = SDL_SwapBE16 (320*input.abs_x/screen_w);
-	mouse_abs[1] = SDL_SwapBE16 (200*input.abs_y/screen_h);
+	mouse_abs[0] = SDL_SwapBE16 (input.abs_x);
+	mouse_abs[1] = SDL_SwapBE16 (input.abs_y);
 
 	//if (input.mbuf_head != input.mbuf_tail) {
 	//	mouse_mov[2] = SDL_SwapBE16 (0xf8 | input.mousebut_buf [input.mbuf_head++]);
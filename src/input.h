@@ -15,7 +15,7 @@ typedef struct {
 	int mbuf_head, mbuf_tail;
 	int cur_mousebut_state;
 
-	/* change in mouse pos since last polled, absolute position */
+	/* change in mouse pos since last polled, absolute position on the 320x200 screen */
 	int motion_x, motion_y;
 	int abs_x, abs_y;
 	/* mouse button state when last polled, and now */
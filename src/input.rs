@@ -1,12 +1,55 @@
+use std::cell::Cell;
+
 use crate::c_lib;
 
 pub struct MouseInput {
     pub motion_x: isize,
     pub motion_y: isize,
-    pub abs_x: usize,
-    pub abs_y: usize,
+    /* in window coordinates */
+    pub abs_x: i32,
+    pub abs_y: i32,
+}
+
+/* from window coordinates to the 320x200 of the ST screen */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MouseTransform {
+    scale_x: f32,
+    scale_y: f32,
+    offset_x: f32,
+    offset_y: f32,
+}
+
+impl MouseTransform {
+    /* where the screen is in the window, from the top left */
+    pub fn new(x: f32, y: f32, w: f32, h: f32) -> Self {
+        Self {
+            scale_x: 320.0 / w.max(1.0),
+            scale_y: 200.0 / h.max(1.0),
+            offset_x: x,
+            offset_y: y,
+        }
+    }
+
+    pub fn apply(&self, x: i32, y: i32) -> (usize, usize) {
+        let st_x = (x as f32 - self.offset_x) * self.scale_x;
+        let st_y = (y as f32 - self.offset_y) * self.scale_y;
+
+        (st_x.clamp(0.0, 319.0) as usize, st_y.clamp(0.0, 199.0) as usize)
+    }
+}
+
+thread_local! {
+    static MOUSE_TRANSFORM: Cell<MouseTransform> = Cell::new(MouseTransform::new(0.0, 0.0, 640.0, 480.0));
+}
+
+/* has to follow the window, a stale transform puts the pointer in the wrong place */
+pub fn set_mouse_transform(transform: MouseTransform) {
+    MOUSE_TRANSFORM.with(|cell| cell.set(transform));
 }
 
 pub fn set_mouse(state: MouseInput) {
-    c_lib::update_mouse_input(state.motion_x, state.motion_y, state.abs_x, state.abs_y);
+    let (abs_x, abs_y) = MOUSE_TRANSFORM.with(|cell| cell.get().apply(state.abs_x, state.abs_y));
+
+    c_lib::update_mouse_input(state.motion_x, state.motion_y, abs_x, abs_y);
 }
+
@@ -17,7 +17,7 @@ pub fn key_down(keycode: Keycode, scancode: Scancode, keymod: sdl2::keyboard::Mo
     // char STScanCode;
     // int symkey = sdlkey->sym; // keycode
 
-    println!("keydown: sym={} scan={} mod=${:x}", keycode, scancode, keymod);
+    trace!("keydown: sym={} scan={} mod=${:x}", keycode, scancode, keymod);
 
     /* Handle special keys */
     match keycode {
@@ -65,7 +65,7 @@ pub fn key_down(keycode: Keycode, scancode: Scancode, keymod: sdl2::keyboard::Mo
 User released key
 */
 pub fn key_up(keycode: Keycode, scancode: Scancode, keymod: keyboard::Mod) {
-    println!("keyup: sym={} scan={} mod=${:x}", keycode, scancode, keymod);
+    trace!("keyup: sym={} scan={} mod=${:x}", keycode, scancode, keymod);
 
     /* Handle special keys */
     match keycode {
@@ -193,7 +193,7 @@ fn pc_to_st_scancode(keycode: Keycode) -> Option<usize> {
         Keycode::F10 => Some(0x44),
 
         _ => {
-            println!("Keymap: received dead keycode {:?}", keycode);
+            debug!("received dead keycode {:?}", keycode);
 
             None
         }
@@ -233,7 +233,7 @@ fn get_keypad_scancode(keycode: Keycode, keymod: keyboard::Mod) -> usize {
                 Keycode::Kp9 => 0x47,  /* Home - again for Dungeon Master */
 
                 _ => {
-                    println!("unexpected keypad keycode {:?}", keycode);
+                    warn!("unexpected keypad keycode {:?}", keycode);
                     0x0
                 },
             }
@@ -0,0 +1,130 @@
+use std::collections::VecDeque;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use log::{ LevelFilter, Log, Metadata, Record };
+
+/* targets default to the module path, we only want the module */
+static CRATE_PREFIX: &str = "final_frontier::";
+
+static RECENT_LEN: usize = 256;
+
+/*
+ * Recent messages are kept down to this level whatever the filter says, and
+ * below it when the filter lets them through. Lower ones would cost
+ * formatting and the lock even with the filter switching them off.
+ */
+static RECENT_LEVEL: LevelFilter = LevelFilter::Info;
+
+lazy_static! {
+    static ref RECENT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::with_capacity(RECENT_LEN));
+}
+
+/*
+ * Parsed from --log, e.g. "warn,audio=debug,keymap=off". A bare level sets
+ * the default, target=level applies to a subsystem and everything below it.
+ */
+#[derive(Debug)]
+pub struct LogFilter {
+    default: LevelFilter,
+    targets: Vec<(String, LevelFilter)>,
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        Self { default: LevelFilter::Info, targets: vec!() }
+    }
+}
+
+impl FromStr for LogFilter {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let mut filter = LogFilter::default();
+
+        for item in spec.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            let parse_level = |level: &str| {
+                level.parse::<LevelFilter>().map_err(|_| format!("unknown log level \"{}\"", level))
+            };
+
+            match item.find('=') {
+                Some(index) => {
+                    let level = parse_level(&item[index + 1..])?;
+                    filter.targets.push((item[..index].to_string(), level));
+                },
+                None => filter.default = parse_level(item)?,
+            }
+        }
+
+        Ok(filter)
+    }
+}
+
+impl LogFilter {
+    fn level_for(&self, target: &str) -> LevelFilter {
+        /* the last matching entry wins, like later arguments do */
+        self.targets.iter().rev()
+            .find(|(name, _)| target == name || target.starts_with(&format!("{}::", name)))
+            .map_or(self.default, |(_, level)| *level)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.targets.iter().map(|(_, level)| *level).fold(self.default, |max, level| max.max(level))
+    }
+}
+
+struct Logger {
+    filter: LogFilter,
+}
+
+fn subsystem(target: &str) -> &str {
+    target.strip_prefix(CRATE_PREFIX).unwrap_or(target)
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= RECENT_LEVEL || metadata.level() <= self.filter.level_for(subsystem(metadata.target()))
+    }
+
+    fn log(&self, record: &Record) {
+        let target = subsystem(record.target());
+        let line = format!("{:<5} {}: {}", record.level(), target, record.args());
+
+        let shown = record.level() <= self.filter.level_for(target);
+
+        if shown {
+            eprintln!("{}", line);
+        }
+
+        if shown || record.level() <= RECENT_LEVEL {
+            let mut recent = RECENT.lock().unwrap_or_else(|error| error.into_inner());
+
+            if recent.len() == RECENT_LEN {
+                recent.pop_front();
+            }
+
+            recent.push_back(line);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+pub fn init(filter: LogFilter) {
+    let max_level = filter.max_level().max(RECENT_LEVEL);
+
+    if log::set_logger(Box::leak(Box::new(Logger { filter }))).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+/*
+ * The most recent messages, oldest first. Used by the crash handler, so
+ * this gives up instead of waiting for the lock.
+ */
+pub fn recent() -> Vec<String> {
+    match RECENT.try_lock() {
+        Ok(recent) => recent.iter().cloned().collect(),
+        Err(_) => vec!(),
+    }
+}
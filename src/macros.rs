@@ -40,7 +40,7 @@ macro_rules! with_audio_context {
 
             $block
         } or {
-            println!("Sound: no audio device initialized");
+            debug!("no audio device initialized");
         });
     }
 }
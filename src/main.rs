@@ -1,27 +1,47 @@
 extern crate sdl2;
 extern crate lewton;
 extern crate rand;
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate lazy_static;
 
 #[macro_use]
 mod macros;
 
 mod c_lib;
+mod cli;
+mod config;
+mod crash;
+mod error;
+mod logging;
+mod offscreen;
+mod osd;
+mod pause;
+mod paths;
+mod savefile;
+mod shutdown;
 mod screen;
 mod keymap;
 mod audio;
 mod input;
 mod shortcut;
+mod timing;
 
 use std::env;
+use std::path::Path;
 use std::process::exit;
-use std::cell::RefCell;
 use std::thread::sleep;
 use std::time::Duration;
+use std::cell::RefCell;
 
 use sdl2::Sdl;
-use sdl2::event::Event;
+use sdl2::event::{ Event, WindowEvent };
 
+use cli::Command;
 use input::MouseInput;
+use config::GameConfig;
+use error::{ Error, Result };
 
 thread_local! {
     static SDL_CONTEXT: RefCell<Option<SdlContext>> = RefCell::default();
@@ -32,31 +52,13 @@ use c_lib::{
     c_Input_MousePress, c_Input_MouseRelease,
 };
 
-pub struct GameConfig {
-    use_fullscreen: bool,
-    nosound: bool,
-    screen_w: u32,
-    screen_h: u32
-}
-
-impl GameConfig {
-    fn new() -> Self {
-        Self {
-            use_fullscreen: false,
-            nosound: false,
-            screen_w: 640,
-            screen_h: 480,
-        }
-    }
-}
-
 pub struct SdlContext {
     base: Sdl,
     video: sdl2::VideoSubsystem,
     timer: sdl2::TimerSubsystem,
     event_pump: sdl2::EventPump,
     mouse: sdl2::mouse::MouseUtil,
-    audio: sdl2::AudioSubsystem,
+    audio: Option<sdl2::AudioSubsystem>,
     event: sdl2::EventSubsystem,
 }
 
@@ -89,114 +91,170 @@ impl SdlContext {
         &self.mouse
     }
 
-    pub fn audio(&self) -> &sdl2::AudioSubsystem {
-        &self.audio
+    pub fn audio(&self) -> Option<&sdl2::AudioSubsystem> {
+        self.audio.as_ref()
     }
 
     pub fn event(&self) -> &sdl2::EventSubsystem {
         &self.event
     }
 
-    fn init() -> Self {
-        let base = sdl2::init().expect("unable to init SDL");
-        let video = base.video().expect("unable to init SDL Video");
-        let timer = base.timer().expect("unable to init SDL Timer");
-        let event_pump = base.event_pump().expect("unable to init SDL Event Pump");
-        let audio = base.audio().expect("unable to init SDL Audio");
+    fn init() -> Result<Self> {
+        let base = sdl2::init().map_err(Error::Sdl)?;
+        let video = base.video().map_err(Error::Video)?;
+        let timer = base.timer().map_err(Error::Sdl)?;
+        let event_pump = base.event_pump().map_err(Error::Sdl)?;
         let mouse = base.mouse();
-        let event = base.event().expect("unable to init SDL event");
+        let event = base.event().map_err(Error::Sdl)?;
+
+        /* audio is optional, the game is playable without it */
+        let audio = match base.audio() {
+            Ok(audio) => Some(audio),
+            Err(error) => {
+                warn!(target: "audio", "unable to init SDL Audio, sound disabled: {}", error);
+                None
+            }
+        };
 
-        Self { base, video, timer, event_pump, mouse, audio, event }
+        Ok(Self { base, video, timer, event_pump, mouse, audio, event })
     }
 }
 
-fn read_parameters(args: Vec<String>) -> GameConfig {
-    let mut config = GameConfig::new();
-    let mut args = args.into_iter();
+fn init(context: &mut SdlContext, config: &GameConfig) -> Result<()> {
+    paths::init(config);
+    screen::init(context, config)?;
 
-    // skip first arg, it's our binary name
-    args.next();
+    // Init CPU emulation
+    c_Init680x0();
+    audio::init(context, config);
+    keymap::init(context);
+    timing::init(config);
+    pause::init(config);
 
-    while let Some(arg) = args.next() {
-        if arg.is_empty() {
-            continue;
-        }
+    if !config.headless {
+        shutdown::add_hook(Box::new(config::save));
+    }
 
-        match &*arg {
-            "--help" | "-h" =>  {
-                println!("Usage:\n frontier [options]\n
-                       Where options are:\n
-                          --help or -h          Print this help text and exit.\n
-                          --fullscreen or -f    Try to use fullscreen mode.\n
-                          --nosound             Disable sound (faster!).\n
-                          --size w            Start at specified window size.\n"
-                      );
-
-                exit(0);
-            }
+    Ok(())
+}
 
-            "--fullscreen" | "-f" => {
-                config.use_fullscreen = true;
-            }
+/*-----------------------------------------------------------------------*/
+/*
+  Un-Initialise emulation
+*/
+pub fn uninit() {
+    audio::uninit();
+    screen::uninit();
 
-            "--nosound" => {
-                config.nosound = true;
-            }
+    SDL_CONTEXT.with(|ref_cell| {
+        ref_cell.borrow_mut().take();
+    });
+}
 
-            "--size" => {
-                if let Some(value) = args.next() {
-                    config.screen_w = u32::from_str_radix(&value, 10).expect("size musst be a number!");
-                    config.screen_h = 5 * config.screen_w / 8;
-                }
-            }
+fn run(config: GameConfig) {
+    if config.headless {
+        /* SDL falls back to these drivers, so no display or sound hardware is needed */
+        env::set_var("SDL_VIDEODRIVER", "dummy");
+        env::set_var("SDL_AUDIODRIVER", "dummy");
+    }
 
-            _ => println!("Illegal parameter: {}", arg)
-        }
+    let mut sdl_context = SdlContext::init().unwrap_or_else(|error| {
+        error!("{}", error);
+        exit(1);
+    });
+
+    if let Err(error) = init(&mut sdl_context, &config) {
+        error!("{}", error);
+        exit(1);
+    }
+
+    let timer_subsystem = sdl_context.timer().clone();
+    let _vbl_timer = if config.vbl_timer {
+        Some(timing::add_vbl_timer(&timer_subsystem))
+    } else {
+        None
     };
 
-    config
+    SDL_CONTEXT.with(|ref_cell| {
+        *ref_cell.borrow_mut() = Some(sdl_context);
+    });
+
+    /* Run emulation */
+    info!("starting 680x0...");
+    c_Start680x0();
+
+    shutdown::run();
 }
 
-fn init(context: &mut SdlContext, config: &GameConfig) {
-    screen::init(context, config);
+fn inspect_save(path: &Path) -> Result<()> {
+    let save = savefile::inspect(path)?;
 
-    // Init CPU emulation
-    c_Init680x0();
-    audio::init(context, config);
-    keymap::init(context);
+    println!("File:       {}", path.display());
+    println!("Size:       {} bytes", save.size);
+    println!("Checksum:   {}", if save.checksum_ok { "ok" } else { "mismatch, the game will refuse it" });
+    println!("Commander:  {}", save.commander);
+    println!("Date:       {}", save.date());
+    println!("Sound:      sfx {}, music {}{}",
+        if save.sfx_on { "on" } else { "off" },
+        if save.music_on { "on" } else { "off" },
+        if save.continuous_music { ", continuous" } else { "" });
+
+    Ok(())
 }
 
-fn main() {
-    let args = env::args();
+fn list_audio_devices() -> Result<()> {
+    let audio = sdl2::init().and_then(|base| base.audio()).map_err(Error::Sdl)?;
 
-    /* Check for any passed parameters */
-    let config = read_parameters(args.collect());
-    let mut sdl_context = SdlContext::init();
+    println!("Audio driver: {}", audio.current_audio_driver());
 
-    init(&mut sdl_context, &config);
+    for (index, name) in audio::list_devices(&audio)?.iter().enumerate() {
+        println!("{}: {}", index, name);
+    }
 
-    // let _timer = sdl_context.timer().add_timer(20, Box::new(vbl_callback));
+    Ok(())
+}
 
-    SDL_CONTEXT.with(|ref_cell| {
-        *ref_cell.borrow_mut() = Some(sdl_context);
-    });
+fn main() {
+    let cli = cli::parse();
+
+    logging::init(cli.log_filter());
+    crash::install();
+
+    /* Load the config file, passed parameters take precedence */
+    let config = cli.apply(config::load(cli.config_path()));
+
+    let result = match cli.command {
+        Command::Run => {
+            run(config);
+            Ok(())
+        },
+        Command::InspectSave(ref path) => inspect_save(path),
+        Command::ListAudioDevices => list_audio_devices(),
+        Command::DumpConfig => {
+            print!("{}", config::to_toml(&config));
+            Ok(())
+        },
+    };
 
-    /* Run emulation */
-    println!("starting 680x0...");
-    c_Start680x0();
+    if let Err(error) = result {
+        error!("{}", error);
+        exit(1);
+    }
 }
 
 pub fn event_handler() {
+    audio::report_errors();
+
     with_static_ref_option! {
-        let sdl_context = { SDL_CONTEXT } or { println!("Main: SDL context not available yet!") };
+        let sdl_context = { SDL_CONTEXT } or { warn!("SDL context not available yet!") };
 
         while let Some(event) = sdl_context.event_pump_mut().poll_event() {
-            println!("handle sdl event {:?}", event);
+            trace!("handle sdl event {:?}", event);
 
             match event {
                 Event::Quit { .. } => {
-                    println!("trying to quit application!");
-                    exit(0);
+                    info!("trying to quit application!");
+                    shutdown::request();
                 },
 
                 // Read/Update internal mouse position
@@ -204,8 +262,8 @@ pub fn event_handler() {
                     let mouse_input = MouseInput {
                         motion_x: xrel as isize,
                         motion_y: yrel as isize,
-                        abs_x: x as usize,
-                        abs_y: y as usize,
+                        abs_x: x,
+                        abs_y: y,
                     };
 
                     input::set_mouse(mouse_input);
@@ -227,6 +285,28 @@ pub fn event_handler() {
                     keymap::key_down(keycode.unwrap(), scancode.unwrap(), keymod, sdl_context)
                 },
 
+                Event::Window { win_event: WindowEvent::FocusLost, .. } |
+                Event::Window { win_event: WindowEvent::Minimized, .. } => {
+                    pause::focus_lost();
+                },
+
+                Event::Window { win_event: WindowEvent::FocusGained, .. } |
+                Event::Window { win_event: WindowEvent::Restored, .. } => {
+                    pause::focus_gained();
+                },
+
+                Event::Window { win_event: WindowEvent::SizeChanged(w, h), .. } => {
+                    screen::resize(w.max(1) as u32, h.max(1) as u32);
+                },
+
+                Event::AudioDeviceAdded { which, iscapture: false, .. } => {
+                    audio::device_added(which);
+                },
+
+                Event::AudioDeviceRemoved { iscapture: false, .. } => {
+                    audio::device_removed();
+                },
+
                 Event::KeyUp { keycode, scancode, keymod, .. } => {
                     if keycode.is_none() {
                         continue;
@@ -236,7 +316,7 @@ pub fn event_handler() {
                 },
 
                 _ => {
-                    println!("ignoring SDL event {:?}", event);
+                    trace!("ignoring SDL event {:?}", event);
                 }
             }
         }
@@ -246,7 +326,16 @@ pub fn event_handler() {
 }
 
 pub fn idle() {
-    c_FlagException(0);
+    /* no VBLs while paused, only keep the window alive */
+    while pause::is_paused() {
+        event_handler();
+        shutdown::check();
+        screen::nu_draw_screen();
+        sleep(Duration::from_millis(100));
+    }
 
-    sleep(Duration::from_millis(20));
+    timing::wait_for_vbl();
+    audio::advance(timing::vbl_period());
+
+    c_FlagException(0);
 }
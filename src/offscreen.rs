@@ -0,0 +1,56 @@
+use std::fs::File;
+use std::io::{ self, Write, BufWriter };
+use std::path::Path;
+
+use crate::c_lib;
+
+/* size of frontier's software rendered screen */
+pub static RASTER_W: usize = 320;
+pub static RASTER_H: usize = 200;
+
+/*
+ * Render target that is used instead of a window when running headless.
+ * Frames are taken straight from the VideoRaster, so only the R_OLD
+ * renderer is able to draw into it.
+ */
+pub struct OffscreenTarget {
+    pixels: Vec<u32>,
+    frame_count: u32,
+}
+
+impl OffscreenTarget {
+    pub fn new() -> Self {
+        Self {
+            pixels: vec![0; RASTER_W * RASTER_H],
+            frame_count: 0,
+        }
+    }
+
+    pub fn frame_count(&self) -> u32 {
+        self.frame_count
+    }
+
+    pub fn draw_frame(&mut self) {
+        c_lib::build_palettes();
+        c_lib::unsafe_copy_video_raster(&mut self.pixels, RASTER_W, RASTER_H);
+
+        self.frame_count += 1;
+    }
+
+    /* dump the current frame as binary PPM */
+    pub fn write_ppm(&self, path: &Path) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+
+        write!(file, "P6\n{} {}\n255\n", RASTER_W, RASTER_H)?;
+
+        for pixel in &self.pixels {
+            let r = (pixel & 0xff) as u8;
+            let g = ((pixel >> 8) & 0xff) as u8;
+            let b = ((pixel >> 16) & 0xff) as u8;
+
+            file.write_all(&[r, g, b])?;
+        }
+
+        file.flush()
+    }
+}
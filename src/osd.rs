@@ -0,0 +1,61 @@
+use std::cell::RefCell;
+use std::time::{ Duration, Instant };
+
+use crate::c_lib;
+
+thread_local! {
+    static OSD: RefCell<Osd> = RefCell::default();
+}
+
+static MESSAGE_DURATION: Duration = Duration::from_secs(2);
+
+struct OsdMessage {
+    text: String,
+    expires: Instant,
+}
+
+/*
+ * On-screen display. The indicator stays until it is cleared, messages
+ * disappear after a while. Both are drawn on top of the finished frame.
+ */
+#[derive(Default)]
+struct Osd {
+    indicator: Option<String>,
+    message: Option<OsdMessage>,
+}
+
+pub fn set_indicator(text: Option<&str>) {
+    OSD.with(|ref_cell| {
+        ref_cell.borrow_mut().indicator = text.map(String::from);
+    });
+}
+
+/* replaces whatever message is shown */
+pub fn show_message(text: &str) {
+    OSD.with(|ref_cell| {
+        ref_cell.borrow_mut().message = Some(OsdMessage {
+            text: text.to_string(),
+            expires: Instant::now() + MESSAGE_DURATION,
+        });
+    });
+}
+
+pub fn draw() {
+    OSD.with(|ref_cell| {
+        let osd = &mut *ref_cell.borrow_mut();
+        let mut ypos = 4;
+
+        if let Some(ref indicator) = osd.indicator {
+            c_lib::c_draw_osd_string(4, ypos, indicator);
+            ypos += 10;
+        }
+
+        if matches!(osd.message, Some(ref message) if message.expires <= Instant::now()) {
+            osd.message = None;
+        }
+
+        if let Some(ref message) = osd.message {
+            c_lib::c_draw_osd_string(4, ypos, &message.text);
+        }
+    });
+}
@@ -0,0 +1,219 @@
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::path::{ Component, Path, PathBuf };
+
+use crate::GameConfig;
+
+static DATA_DIR_NAME: &str = "final-frontier";
+static SAVE_DIR_NAME: &str = "savs";
+
+/* fe2.s starts the file selector here, see L72cf3_default_drv */
+static GAME_SAVE_DIR: &str = "./savs";
+
+/* a directory is only taken as data dir if it has one of these */
+static DATA_MARKERS: [&str; 2] = ["sfx", "music"];
+
+thread_local! {
+    static PATHS: RefCell<Option<Paths>> = RefCell::default();
+}
+
+struct Paths {
+    data_dir: PathBuf,
+    save_dir: PathBuf,
+}
+
+fn is_data_dir(dir: &Path) -> bool {
+    DATA_MARKERS.iter().any(|marker| dir.join(marker).is_dir())
+}
+
+/*
+ * The XDG data dir comes first, then the directory of the executable and
+ * the share dir of the prefix it is installed to. The current directory
+ * is where we used to look, so it is the last resort.
+ */
+fn data_dir_candidates() -> Vec<PathBuf> {
+    let mut candidates = vec!();
+
+    if let Some(dir) = dirs::data_dir() {
+        candidates.push(dir.join(DATA_DIR_NAME));
+    }
+
+    if let Some(dir) = env::current_exe().ok().as_ref().and_then(|exe| exe.parent()) {
+        candidates.push(dir.to_path_buf());
+        candidates.push(dir.join("..").join("share").join(DATA_DIR_NAME));
+    }
+
+    candidates.push(PathBuf::from("."));
+    candidates
+}
+
+fn resolve_data_dir(config: &GameConfig) -> PathBuf {
+    if let Some(ref dir) = config.data_dir {
+        if !is_data_dir(dir) {
+            warn!("{} has no sfx or music directory", dir.display());
+        }
+
+        return dir.clone();
+    }
+
+    data_dir_candidates().into_iter()
+        .find(|dir| is_data_dir(dir))
+        .unwrap_or_else(|| {
+            warn!("no data directory found, using the current directory");
+            PathBuf::from(".")
+        })
+}
+
+fn resolve_save_dir(config: &GameConfig, data_dir: &Path) -> PathBuf {
+    if let Some(ref dir) = config.save_dir {
+        return dir.clone();
+    }
+
+    dirs::data_local_dir()
+        .map(|dir| dir.join(DATA_DIR_NAME).join(SAVE_DIR_NAME))
+        .unwrap_or_else(|| data_dir.join(SAVE_DIR_NAME))
+}
+
+fn copy_saves(old_dir: &Path, save_dir: &Path) {
+    let entries = match fs::read_dir(old_dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        let target = save_dir.join(entry.file_name());
+
+        /* both old dirs can have the same commander, the first one wins */
+        if !path.is_file() || target.exists() {
+            continue;
+        }
+
+        if let Err(error) = fs::copy(&path, &target) {
+            warn!("unable to copy {}: {}", path.display(), error);
+        }
+    }
+
+    info!("copied saves from {} to {}", old_dir.display(), save_dir.display());
+}
+
+/*
+ * Saves used to be written to ./savs relative to wherever the game was
+ * started, usually next to the data. Copy them over the first time the
+ * per-user save dir is created so nobody loses their commander.
+ */
+fn create_save_dir(save_dir: &Path, data_dir: &Path) {
+    if save_dir.is_dir() {
+        return;
+    }
+
+    if let Err(error) = fs::create_dir_all(save_dir) {
+        error!("unable to create save directory {}: {}", save_dir.display(), error);
+        return;
+    }
+
+    let game_dir = PathBuf::from(GAME_SAVE_DIR);
+    let data_save_dir = data_dir.join(SAVE_DIR_NAME);
+
+    copy_saves(&game_dir, save_dir);
+
+    let same_dir = match (game_dir.canonicalize(), data_save_dir.canonicalize()) {
+        (Ok(game_dir), Ok(data_save_dir)) => game_dir == data_save_dir,
+        _ => false,
+    };
+
+    if !same_dir {
+        copy_saves(&data_save_dir, save_dir);
+    }
+}
+
+pub fn init(config: &GameConfig) {
+    let data_dir = resolve_data_dir(config);
+    let save_dir = resolve_save_dir(config, &data_dir);
+
+    if !config.headless {
+        create_save_dir(&save_dir, &data_dir);
+    }
+
+    info!("data in {}, saves in {}", data_dir.display(), save_dir.display());
+
+    PATHS.with(|ref_cell| {
+        *ref_cell.borrow_mut() = Some(Paths { data_dir, save_dir });
+    });
+}
+
+/* a file that ships with the game, e.g. sfx/sfx_00.wav */
+pub fn data_file<P: AsRef<Path>>(relative: P) -> PathBuf {
+    PATHS.with(|ref_cell| match *ref_cell.borrow() {
+        Some(ref paths) => paths.data_dir.join(relative),
+        None => relative.as_ref().to_path_buf(),
+    })
+}
+
+pub fn save_dir() -> PathBuf {
+    PATHS.with(|ref_cell| match *ref_cell.borrow() {
+        Some(ref paths) => paths.save_dir.clone(),
+        None => PathBuf::from(GAME_SAVE_DIR),
+    })
+}
+
+/*
+ * Maps a path as the 68k code sees it to the host. The game's ./savs is
+ * the per-user save dir and every other relative path is taken to be in
+ * it as well, so the file selector can't get anywhere else. None for
+ * absolute paths and anything that climbs up.
+ */
+pub fn host_path(game_path: &str) -> Option<PathBuf> {
+    let path = Path::new(game_path);
+
+    if !path.components().all(|component| matches!(component, Component::CurDir | Component::Normal(_))) {
+        warn!("{} is outside of the save directory", game_path);
+        return None;
+    }
+
+    let relative: PathBuf = path.components().filter(|&component| component != Component::CurDir).collect();
+    let relative = relative.strip_prefix(SAVE_DIR_NAME).unwrap_or(&relative);
+
+    if relative.as_os_str().is_empty() {
+        return Some(save_dir());
+    }
+
+    Some(save_dir().join(relative))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_save_dir(save_dir: &str) {
+        PATHS.with(|ref_cell| {
+            *ref_cell.borrow_mut() = Some(Paths { data_dir: PathBuf::from("data"), save_dir: PathBuf::from(save_dir) });
+        });
+    }
+
+    #[test]
+    fn the_game_save_dir_is_the_user_one() {
+        with_save_dir("/home/user/saves");
+
+        assert_eq!(host_path("./savs"), Some(PathBuf::from("/home/user/saves")));
+        assert_eq!(host_path("./savs/JAMESON._01"), Some(PathBuf::from("/home/user/saves/JAMESON._01")));
+    }
+
+    #[test]
+    fn other_relative_paths_stay_in_the_save_dir() {
+        with_save_dir("/home/user/saves");
+
+        assert_eq!(host_path("JAMESON._01"), Some(PathBuf::from("/home/user/saves/JAMESON._01")));
+        assert_eq!(host_path("./other/x"), Some(PathBuf::from("/home/user/saves/other/x")));
+    }
+
+    #[test]
+    fn paths_out_of_the_save_dir_are_refused() {
+        with_save_dir("/home/user/saves");
+
+        assert_eq!(host_path("../x"), None);
+        assert_eq!(host_path("./savs/../../x"), None);
+        assert_eq!(host_path("/etc/passwd"), None);
+    }
+}
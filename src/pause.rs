@@ -0,0 +1,103 @@
+use std::cell::RefCell;
+
+use crate::GameConfig;
+use crate::audio;
+use crate::osd;
+use crate::timing;
+
+thread_local! {
+    static PAUSE_STATE: RefCell<PauseState> = RefCell::default();
+}
+
+#[derive(Default)]
+struct PauseState {
+    paused: bool,
+    /* paused because the window lost focus, resumes when it comes back */
+    auto_paused: bool,
+    pause_on_focus_loss: bool,
+}
+
+pub fn init(config: &GameConfig) {
+    PAUSE_STATE.with(|ref_cell| {
+        ref_cell.borrow_mut().pause_on_focus_loss = config.pause_on_focus_loss;
+    });
+}
+
+pub fn is_paused() -> bool {
+    PAUSE_STATE.with(|ref_cell| ref_cell.borrow().paused)
+}
+
+/*-----------------------------------------------------------------------*/
+/*
+  Pause emulation, stop sound
+*/
+pub fn pause_emulation() {
+    let was_paused = PAUSE_STATE.with(|ref_cell| {
+        let state = &mut *ref_cell.borrow_mut();
+
+        std::mem::replace(&mut state.paused, true)
+    });
+
+    if was_paused {
+        return;
+    }
+
+    audio::set_enabled(false);
+    osd::set_indicator(Some("PAUSED"));
+}
+
+/*-----------------------------------------------------------------------*/
+/*
+  Start emulation
+*/
+pub fn unpause_emulation() {
+    let was_paused = PAUSE_STATE.with(|ref_cell| {
+        let state = &mut *ref_cell.borrow_mut();
+
+        state.auto_paused = false;
+        std::mem::replace(&mut state.paused, false)
+    });
+
+    if !was_paused {
+        return;
+    }
+
+    osd::set_indicator(None);
+    audio::set_enabled(true);
+
+    /* don't try to catch up on the VBLs we missed while paused */
+    timing::resync();
+}
+
+pub fn toggle_pause() {
+    if is_paused() {
+        unpause_emulation();
+    } else {
+        pause_emulation();
+    }
+}
+
+pub fn focus_lost() {
+    let should_pause = PAUSE_STATE.with(|ref_cell| {
+        let state = &mut *ref_cell.borrow_mut();
+        let should_pause = state.pause_on_focus_loss && !state.paused;
+
+        if should_pause {
+            state.auto_paused = true;
+        }
+
+        should_pause
+    });
+
+    if should_pause {
+        pause_emulation();
+    }
+}
+
+pub fn focus_gained() {
+    let auto_paused = PAUSE_STATE.with(|ref_cell| ref_cell.borrow().auto_paused);
+
+    if auto_paused {
+        unpause_emulation();
+    }
+}
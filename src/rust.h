@@ -1,2 +1,8 @@
 extern void Call_Idle ();
 extern void Nu_DrawScreen();
+extern void Call_Fread ();
+extern void Call_Fwrite ();
+extern void Call_Fdelete ();
+extern void Call_Fopendir ();
+extern void Call_Freaddir ();
+extern void Call_Fclosedir ();
@@ -0,0 +1,281 @@
+use std::cell::RefCell;
+use std::fs::{ self, File };
+use std::io::{ self, Read };
+use std::path::Path;
+
+use crate::error::{ Error, Result };
+use crate::paths;
+
+/* fe2.s reserves this much for a name at a0, including the terminator */
+pub static MAX_FILENAME_LEN: usize = 14;
+
+/* GEMDOS file attribute of a directory */
+static ATTRIB_DIR: i32 = 0x10;
+
+thread_local! {
+    static OPEN_DIR: RefCell<Option<fs::ReadDir>> = RefCell::default();
+}
+
+pub struct DirEntry {
+    /* zero padded to MAX_FILENAME_LEN */
+    pub name: Vec<u8>,
+    pub attribs: i32,
+    pub len: i32,
+}
+
+fn read_to_buffer(file: &mut File, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut total = 0;
+
+    while total < buffer.len() {
+        match file.read(&mut buffer[total..])? {
+            0 => break,
+            count => total += count,
+        }
+    }
+
+    Ok(total)
+}
+
+/* returns the number of bytes read, 0 if the file can't be read */
+pub fn read(game_path: &str, buffer: &mut [u8]) -> usize {
+    let path = match paths::host_path(game_path) {
+        Some(path) => path,
+        None => return 0,
+    };
+
+    match File::open(&path).and_then(|mut file| read_to_buffer(&mut file, buffer)) {
+        Ok(count) => count,
+        Err(error) => {
+            warn!("unable to read {}: {}", path.display(), error);
+            0
+        }
+    }
+}
+
+/* returns the number of bytes written, 0 if the file can't be written */
+pub fn write(game_path: &str, buffer: &[u8]) -> usize {
+    let path = match paths::host_path(game_path) {
+        Some(path) => path,
+        None => return 0,
+    };
+
+    match fs::write(&path, buffer) {
+        Ok(()) => buffer.len(),
+        Err(error) => {
+            error!("unable to write {}: {}", path.display(), error);
+            0
+        }
+    }
+}
+
+pub fn delete(game_path: &str) -> bool {
+    let path = match paths::host_path(game_path) {
+        Some(path) => path,
+        None => return false,
+    };
+
+    match fs::remove_file(&path) {
+        Ok(()) => true,
+        Err(error) => {
+            warn!("unable to delete {}: {}", path.display(), error);
+            false
+        }
+    }
+}
+
+pub fn open_dir(game_path: &str) -> bool {
+    /* a failed open still closes the previous dir */
+    let dir = paths::host_path(game_path).and_then(|path| match fs::read_dir(&path) {
+        Ok(dir) => Some(dir),
+        Err(error) => {
+            warn!("unable to open {}: {}", path.display(), error);
+            None
+        }
+    });
+    let opened = dir.is_some();
+
+    OPEN_DIR.with(|ref_cell| {
+        *ref_cell.borrow_mut() = dir;
+    });
+
+    opened
+}
+
+/* unlike readdir, '.' and '..' never show up here */
+pub fn read_dir() -> Option<DirEntry> {
+    OPEN_DIR.with(|ref_cell| {
+        let dir = ref_cell.borrow_mut().as_mut()?.filter_map(|entry| entry.ok()).next()?;
+        let metadata = dir.metadata().ok();
+
+        let mut name = dir.file_name().to_string_lossy().into_owned().into_bytes();
+        name.resize(MAX_FILENAME_LEN - 1, 0);
+        name.push(0);
+
+        Some(DirEntry {
+            name,
+            attribs: if matches!(metadata, Some(ref metadata) if metadata.is_dir()) { ATTRIB_DIR } else { 0 },
+            len: metadata.map_or(0, |metadata| metadata.len() as i32),
+        })
+    })
+}
+
+pub fn close_dir() {
+    OPEN_DIR.with(|ref_cell| {
+        ref_cell.borrow_mut().take();
+    });
+}
+
+/*
+ * The layout written by L73038_MakeSaveData: a $11 word, then the packed
+ * game state, scrambled by L73164_EncryptSaveData, then the final key of
+ * the scrambler as checksum.
+ */
+static SAVE_MAGIC: u16 = 0x11;
+static SAVE_KEY: u32 = 0x1235_0fd4;
+
+static BIG_SPACE_LEN: usize = 0x80ee;
+static A6_RAW_LEN: usize = 0x20c;
+static A6_PACKED_LEN: usize = 0x3662;
+
+/* offsets into the A6 variables, see the equs at the top of fe2.s */
+static A6_TIME_OF_DAY: usize = 524;
+static A6_DAY_SINCE_EPOCH: usize = 528;
+static A6_OPT_SFX_ON: usize = 10449;
+static A6_OPT_MUSIC_ON: usize = 10454;
+static A6_OPT_CONTIN_MUSIC: usize = 10458;
+static A6_COMMANDER_NAME: usize = 14400;
+
+static MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+pub struct SaveInfo {
+    pub size: usize,
+    pub checksum_ok: bool,
+    pub commander: String,
+    pub day_since_epoch: u32,
+    /* fraction of the day, 1 << 32 is a whole one */
+    pub time_of_day: u32,
+    pub sfx_on: bool,
+    pub music_on: bool,
+    pub continuous_music: bool,
+}
+
+impl SaveInfo {
+    /* the same calendar as L3e1ce, which has a leap year every 4 years */
+    pub fn date(&self) -> String {
+        let cycles = self.day_since_epoch / 1461;
+        let mut year = cycles * 4;
+        let mut day = self.day_since_epoch % 1461;
+
+        if day >= 366 {
+            day -= 366;
+            year += 1 + day / 365;
+            day %= 365;
+
+            /* no 29th of February */
+            if day >= 59 {
+                day += 1;
+            }
+        }
+
+        let month_lengths = [31, 29, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+        let mut month = 0;
+
+        while day >= month_lengths[month] {
+            day -= month_lengths[month];
+            month += 1;
+        }
+
+        let minutes = (self.time_of_day as u64 * 24 * 60) >> 32;
+
+        format!("{:02}-{}-{} {:02}:{:02}", day + 1, MONTHS[month], year, minutes / 60, minutes % 60)
+    }
+}
+
+fn descramble(data: &mut [u8]) -> u32 {
+    let mut key = SAVE_KEY;
+
+    for word in data.chunks_exact_mut(2) {
+        let value = u16::from_be_bytes([word[0], word[1]]) ^ key as u16;
+
+        word.copy_from_slice(&value.to_be_bytes());
+        key = key.wrapping_add(value as i16 as i32 as u32).rotate_left(1);
+    }
+
+    key
+}
+
+/*
+ * A zero byte is followed by the number of zeros that come after it, as
+ * written by the loops in L73038_MakeSaveData.
+ */
+fn unpack<I: Iterator<Item = u8>>(input: &mut I, len: usize) -> Vec<u8> {
+    let mut output = Vec::with_capacity(len);
+    let mut left = len;
+
+    while left > 0 {
+        let byte = match input.next() {
+            Some(byte) => byte,
+            None => break,
+        };
+
+        output.push(byte);
+        left -= 1;
+
+        if byte != 0 {
+            continue;
+        }
+
+        let zeros = input.next().unwrap_or(0) as usize;
+
+        if zeros > left {
+            break;
+        }
+
+        output.resize(output.len() + zeros, 0);
+        left -= zeros;
+    }
+
+    output.resize(len, 0);
+    output
+}
+
+pub fn inspect(path: &Path) -> Result<SaveInfo> {
+    let mut data = fs::read(path).map_err(|error| Error::Savefile(path.to_path_buf(), error.to_string()))?;
+    let size = data.len();
+
+    if size < 6 || u16::from_be_bytes([data[0], data[1]]) != SAVE_MAGIC {
+        return Err(Error::Savefile(path.to_path_buf(), "not a savefile".into()));
+    }
+
+    let checksum = u32::from_be_bytes([data[size - 4], data[size - 3], data[size - 2], data[size - 1]]);
+    let payload = &mut data[2..size - 4];
+    let checksum_ok = descramble(payload) == checksum;
+
+    let mut input = payload.iter().cloned();
+    let _big_space = unpack(&mut input, BIG_SPACE_LEN);
+    let mut a6_vars: Vec<u8> = input.by_ref().take(A6_RAW_LEN).collect();
+    a6_vars.extend(unpack(&mut input, A6_PACKED_LEN));
+
+    /* the packed part is padded, a truncated file only shows in the raw one */
+    if a6_vars.len() < A6_RAW_LEN + A6_PACKED_LEN {
+        return Err(Error::Savefile(path.to_path_buf(), "truncated".into()));
+    }
+
+    let read_long = |offset: usize| {
+        u32::from_be_bytes([a6_vars[offset], a6_vars[offset + 1], a6_vars[offset + 2], a6_vars[offset + 3]])
+    };
+
+    let name = &a6_vars[A6_COMMANDER_NAME..];
+    let name = &name[..name.iter().position(|&byte| byte == 0).unwrap_or(name.len())];
+
+    Ok(SaveInfo {
+        size,
+        checksum_ok,
+        commander: String::from_utf8_lossy(name).into_owned(),
+        day_since_epoch: read_long(A6_DAY_SINCE_EPOCH),
+        time_of_day: read_long(A6_TIME_OF_DAY),
+        sfx_on: a6_vars[A6_OPT_SFX_ON] != 0,
+        music_on: a6_vars[A6_OPT_MUSIC_ON] != 0,
+        continuous_music: a6_vars[A6_OPT_CONTIN_MUSIC] != 0,
+    })
+}
@@ -66,6 +66,9 @@ float hack;
 */
 int screen_w = 640;
 int screen_h = 480;
+/* where the screen starts in the window, for letterboxing */
+int screen_x = 0;
+int screen_y = 0;
 #define GLERR { printf ("GL: %s\n", gluErrorString (glGetError ()));}
 
 #ifndef CALLBACK
@@ -87,12 +90,12 @@ void CALLBACK combineCallback(GLdouble coords[3],
 void set_main_viewport ()
 {
 	int ctrl_h = 32*screen_h/200;
-	glViewport (0, ctrl_h, screen_w, screen_h - ctrl_h);
+	glViewport (screen_x, screen_y + ctrl_h, screen_w, screen_h - ctrl_h);
 }
 
 static void set_ctrl_viewport ()
 {
-	glViewport (0, 0, screen_w, screen_h);
+	glViewport (screen_x, screen_y, screen_w, screen_h);
 }
 
 static void change_vidmode ()
@@ -478,6 +481,47 @@ void draw_control_panel ()
 	pop_ortho ();
 }
 
+/* Draws a string over the finished frame using the fe2 font, without
+ * touching ST screen memory. Used for the on-screen display. */
+static void draw_osd_glyphs (int xpos, int ypos, const unsigned char *str)
+{
+	const unsigned char *font_pos;
+	int x, i, j, chr;
+
+	for (x = xpos; *str; str++) {
+		chr = *str;
+		if ((chr < 0x20) || (chr > 0x7f)) continue;
+		font_pos = font_bmp + (chr-0x20)*10;
+
+		for (i=0; i<8; i++) {
+			for (j=0; j<8; j++) {
+				if (!(font_pos[i] & (0x80>>j))) continue;
+				glVertex2i (x+j, 199-(ypos+i));
+				glVertex2i (x+j+1, 199-(ypos+i));
+				glVertex2i (x+j+1, 200-(ypos+i));
+				glVertex2i (x+j, 200-(ypos+i));
+			}
+		}
+		/* width of character */
+		x += font_pos[9];
+	}
+}
+
+void draw_osd_string (int xpos, int ypos, const char *str)
+{
+	push_ortho ();
+	set_ctrl_viewport ();
+
+	glBegin (GL_QUADS);
+		glColor3f (0.0f, 0.0f, 0.0f);
+		draw_osd_glyphs (xpos+1, ypos+1, (const unsigned char *) str);
+		glColor3f (1.0f, 1.0f, 1.0f);
+		draw_osd_glyphs (xpos, ypos, (const unsigned char *) str);
+	glEnd ();
+
+	pop_ortho ();
+}
+
 static void _BuildRGBPalette (unsigned int *rgb, unsigned short *st, int len)
 {
 	int i;
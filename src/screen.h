@@ -80,6 +80,8 @@ extern void Nu_Put2DLine ();
 
 extern int screen_w;
 extern int screen_h;
+extern int screen_x;
+extern int screen_y;
 extern int mouse_shown;
 extern float hack;
 
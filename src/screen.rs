@@ -1,4 +1,5 @@
 use std::cell::RefCell;
+use std::path::PathBuf;
 
 use sdl2::event::EventType;
 use sdl2::video::GLContext;
@@ -6,8 +7,14 @@ use sdl2::video::Window;
 use sdl2::video::FullscreenType;
 
 use crate::{ SdlContext, GameConfig };
+use crate::error::{ Error, Result };
+use crate::config;
+use crate::shutdown;
+use crate::config::{ Renderer, ScaleMode };
+use crate::input::{ self, MouseTransform };
+use crate::offscreen::OffscreenTarget;
 use crate::c_lib::{
-    glu_init, init_viewport_gl, unsafe_nu_draw_screen, c_get_renderer,
+    glu_init, init_viewport_gl, resize_viewport_gl, unsafe_nu_draw_screen, c_get_renderer,
     c_get_max_renderer, c_set_renderer
 };
 
@@ -15,12 +22,88 @@ thread_local! {
     static SCREEN_CONTEXT: RefCell<Option<ScreenContext>> = RefCell::default();
 }
 
-struct ScreenContext {
-    gl_context: GLContext,
-    window: Window,
+enum ScreenContext {
+    Window {
+        gl_context: GLContext,
+        window: Window,
+        scale_mode: ScaleMode,
+    },
+
+    Offscreen {
+        target: OffscreenTarget,
+        frame_limit: Option<u32>,
+        frame_dump: Option<PathBuf>,
+    },
+}
+
+/* where the 320x200 screen goes in the drawable, in pixels from the bottom left like GL */
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Viewport {
+    x: i32,
+    y: i32,
+    w: u32,
+    h: u32,
+}
+
+impl Viewport {
+    fn fit(scale_mode: ScaleMode, drawable_w: u32, drawable_h: u32) -> Self {
+        let (w, h) = match scale_mode {
+            ScaleMode::Stretch => (drawable_w, drawable_h),
+            ScaleMode::Letterbox => fit_aspect(drawable_w, drawable_h, 4, 3),
+            ScaleMode::Integer => {
+                let scale = (drawable_w / 320).min(drawable_h / 200);
+
+                /* smaller than the ST screen, at least keep its shape */
+                if scale == 0 {
+                    fit_aspect(drawable_w, drawable_h, 320, 200)
+                } else {
+                    (320 * scale, 200 * scale)
+                }
+            },
+        };
+
+        Self {
+            x: ((drawable_w - w) / 2) as i32,
+            y: ((drawable_h - h) / 2) as i32,
+            w,
+            h,
+        }
+    }
+}
+
+/* the largest size with the aspect ratio that fits into w x h */
+fn fit_aspect(w: u32, h: u32, aspect_w: u32, aspect_h: u32) -> (u32, u32) {
+    if w * aspect_h > h * aspect_w {
+        (h * aspect_w / aspect_h, h)
+    } else {
+        (w, w * aspect_h / aspect_w)
+    }
+}
+
+/* the GL viewport and the mouse mapping for the current size of the window */
+fn fit_screen(window: &Window, scale_mode: ScaleMode) {
+    let (window_w, window_h) = window.size();
+    let (drawable_w, drawable_h) = window.drawable_size();
+    let viewport = Viewport::fit(scale_mode, drawable_w, drawable_h);
+
+    debug!("window {}x{}, drawable {}x{}, screen at {:?}", window_w, window_h, drawable_w, drawable_h, viewport);
+
+    resize_viewport_gl(viewport.x, viewport.y, viewport.w, viewport.h);
+
+    /* the mouse is in window coordinates from the top, with HiDPI those aren't pixels */
+    let scale_x = window_w as f32 / drawable_w.max(1) as f32;
+    let scale_y = window_h as f32 / drawable_h.max(1) as f32;
+    let top = drawable_h - viewport.h - viewport.y as u32;
+
+    input::set_mouse_transform(MouseTransform::new(
+        viewport.x as f32 * scale_x,
+        top as f32 * scale_y,
+        viewport.w as f32 * scale_x,
+        viewport.h as f32 * scale_y,
+    ));
 }
 
-pub fn init_viewport(context: &SdlContext, config: &GameConfig) {
+pub fn init_viewport(context: &SdlContext, config: &GameConfig) -> Result<()> {
     let sdl_gl_attr = context.video().gl_attr();
 
     sdl_gl_attr.set_double_buffer(true);
@@ -28,26 +111,55 @@ pub fn init_viewport(context: &SdlContext, config: &GameConfig) {
     let mut sdl_window_builder = context.video().window("Frontier", config.screen_w, config.screen_h);
 
     sdl_window_builder.position_centered()
+        .resizable()
         .opengl();
 
     if config.use_fullscreen {
         sdl_window_builder.fullscreen();
     }
 
-    let window = sdl_window_builder.build().expect("unable to create SDL window");
-    let gl_context = window.gl_create_context().expect("Screen");
+    let window = sdl_window_builder.build()?;
+    let gl_context = window.gl_create_context().map_err(Error::Video)?;
+
+    init_viewport_gl();
+    fit_screen(&window, config.scale_mode);
+
+    let context = ScreenContext::Window { gl_context, window, scale_mode: config.scale_mode };
+
+    SCREEN_CONTEXT.with(|ref_cell| {
+        *ref_cell.borrow_mut() = Some(context);
+    });
+
+    Ok(())
+}
 
-    init_viewport_gl(config);
+/*
+ * Headless mode has no window and no GL context, frames are converted
+ * from the VideoRaster of the software renderer instead.
+ */
+fn init_offscreen(config: &GameConfig) {
+    let context = ScreenContext::Offscreen {
+        target: OffscreenTarget::new(),
+        frame_limit: config.headless_frames,
+        frame_dump: config.frame_dump.clone(),
+    };
 
-    let context = ScreenContext { gl_context, window };
+    c_set_renderer(Renderer::Old.to_c());
 
     SCREEN_CONTEXT.with(|ref_cell| {
         *ref_cell.borrow_mut() = Some(context);
     });
 }
 
-pub fn init(context: &mut SdlContext, config: &GameConfig) {
-	init_viewport(context, config);
+pub fn init(context: &mut SdlContext, config: &GameConfig) -> Result<()> {
+    if config.headless {
+        info!("running headless");
+        init_offscreen(config);
+
+        return Ok(());
+    }
+
+	init_viewport(context, config)?;
 
     glu_init();
 
@@ -56,16 +168,49 @@ pub fn init(context: &mut SdlContext, config: &GameConfig) {
     context.event_pump_mut().enable_event(EventType::MouseButtonDown);
     context.event_pump_mut().enable_event(EventType::MouseButtonUp);
     context.mouse().show_cursor(true);
+    context.mouse().set_relative_mouse_mode(config.grab_mouse);
+
+    c_set_renderer(config.renderer.to_c());
+
+    Ok(())
 }
 
 pub fn nu_draw_screen() {
     with_static_ref_option! {
-        let context = { SCREEN_CONTEXT } or { println!("no screen context available yet!"); };
+        let context = { SCREEN_CONTEXT } or { warn!("no screen context available yet!"); };
+
+        match context {
+            ScreenContext::Window { window, .. } => unsafe_nu_draw_screen(window),
 
-        unsafe_nu_draw_screen(&context.window)
+            ScreenContext::Offscreen { target, frame_limit, frame_dump } => {
+                target.draw_frame();
+
+                let limit_reached = matches!(frame_limit, Some(limit) if target.frame_count() >= *limit);
+
+                if !limit_reached {
+                    return;
+                }
+
+                if let Some(path) = frame_dump {
+                    if let Err(error) = target.write_ppm(path) {
+                        error!("unable to write frame dump {}: {}", path.display(), error);
+                    }
+                }
+
+                info!("rendered {} frames, stopping", target.frame_count());
+                shutdown::request();
+            }
+        }
     }
 }
 
+pub fn uninit() {
+    SCREEN_CONTEXT.with(|ref_cell| {
+        /* the GL context is dropped before its window */
+        ref_cell.borrow_mut().take();
+    });
+}
+
 pub fn build_rgb_palette(rgb_palette: &mut [u32], st_palette: &[u16], st_palette_len: usize) {
 	for  i in 0..st_palette_len {
 		let st_col = st_palette[i];
@@ -79,9 +224,12 @@ pub fn build_rgb_palette(rgb_palette: &mut [u32], st_palette: &[u16], st_palette
 }
 
 pub fn toggle_fullscreen() {
-    with_static_ref_option! {
-        let context = { SCREEN_CONTEXT } or { println!("no screen context available!"); };
-        let current_state = context.window.fullscreen_state();
+    with_static_ref_option!([SCREEN_CONTEXT => context] {
+        let window = match context {
+            ScreenContext::Window { window, .. } => window,
+            ScreenContext::Offscreen { .. } => return
+        };
+        let current_state = window.fullscreen_state();
 
         let new_state = match current_state {
             FullscreenType::True => FullscreenType::Off,
@@ -89,8 +237,37 @@ pub fn toggle_fullscreen() {
             FullscreenType::Off => FullscreenType::Desktop,
         };
 
-        context.window.set_fullscreen(new_state).expect("unable to set fullscreen")
-    };
+        if let Err(error) = window.set_fullscreen(new_state) {
+            error!("unable to switch fullscreen mode: {}", error);
+            return;
+        }
+
+        config::update(|config| config.use_fullscreen = new_state != FullscreenType::Off);
+    } or {
+        warn!("no screen context available!");
+    });
+}
+
+/* the window has a new size, in window coordinates */
+pub fn resize(window_w: u32, window_h: u32) {
+    with_static_ref_option!([SCREEN_CONTEXT => context] {
+        let (window, scale_mode) = match context {
+            ScreenContext::Window { window, scale_mode, .. } => (window, *scale_mode),
+            ScreenContext::Offscreen { .. } => return
+        };
+
+        fit_screen(window, scale_mode);
+
+        /* the fullscreen size is the desktop's, not one to come back to */
+        if window.fullscreen_state() == FullscreenType::Off {
+            config::update(|config| {
+                config.screen_w = window_w;
+                config.screen_h = window_h;
+            });
+        }
+    } or {
+        warn!("no screen context available!");
+    });
 }
 
 pub fn toggle_renderer() {
@@ -104,4 +281,5 @@ pub fn toggle_renderer() {
     };
 
     c_set_renderer(new_renderer);
+    config::update(|config| config.renderer = Renderer::from_c(new_renderer));
 }
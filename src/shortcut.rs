@@ -1,11 +1,17 @@
-use std::process::exit;
-
 use sdl2::keyboard::Keycode;
 
 use crate::SdlContext;
 use crate::screen;
+use crate::config;
+use crate::shutdown;
+use crate::pause;
+use crate::osd;
+use crate::audio::{ self, VolumeControl };
 use crate::c_lib::{ c_Call_DumpDebug };
 
+/* in percent */
+static VOLUME_STEP: i32 = 10;
+
 pub struct ShortcutKey {
     shift_pressed: bool,
     key: Keycode,
@@ -37,14 +43,29 @@ pub fn keychecks(key: ShortcutKey, context: &mut SdlContext) {
     match key.key {
        Keycode::F11 => screen::toggle_fullscreen(), // Switch between fullscreen/windowed mode
        Keycode::M => mouse_mode(context),                  // Toggle mouse mode
-       Keycode::Q => exit(0),                       // Quit program
+       Keycode::Q => shutdown::request(),           // Quit program
        Keycode::D => c_Call_DumpDebug(),
        Keycode::E => screen::toggle_renderer(),
+       Keycode::P => pause::toggle_pause(),         // Pause/resume emulation
+       Keycode::Minus => change_volume(VolumeControl::Master, -VOLUME_STEP),
+       Keycode::Equals => change_volume(VolumeControl::Master, VOLUME_STEP),
+       Keycode::LeftBracket => change_volume(VolumeControl::Music, -VOLUME_STEP),
+       Keycode::RightBracket => change_volume(VolumeControl::Music, VOLUME_STEP),
+       Keycode::Semicolon => change_volume(VolumeControl::Sfx, -VOLUME_STEP),
+       Keycode::Quote => change_volume(VolumeControl::Sfx, VOLUME_STEP),
        _ => {}
     }
 }
 
 fn mouse_mode(context: &mut SdlContext) {
-    context.mouse
-        .set_relative_mouse_mode(!context.mouse.relative_mouse_mode());
+    let grab_mouse = !context.mouse.relative_mouse_mode();
+
+    context.mouse.set_relative_mouse_mode(grab_mouse);
+    config::update(|config| config.grab_mouse = grab_mouse);
+}
+
+fn change_volume(control: VolumeControl, step: i32) {
+    let level = audio::change_volume(control, step);
+
+    osd::show_message(&format!("{}: {}%", control.name(), level));
 }
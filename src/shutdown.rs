@@ -0,0 +1,54 @@
+use std::cell::{ Cell, RefCell };
+use std::process::exit;
+
+type ShutdownHook = Box<dyn FnOnce()>;
+
+thread_local! {
+    static SHUTDOWN_REQUESTED: Cell<bool> = Cell::default();
+    static SHUTDOWN_HOOKS: RefCell<Vec<ShutdownHook>> = RefCell::default();
+}
+
+/*
+ * Registers a function that runs before the subsystems are torn down,
+ * e.g. to persist state.
+ */
+pub fn add_hook(hook: ShutdownHook) {
+    SHUTDOWN_HOOKS.with(|hooks| hooks.borrow_mut().push(hook));
+}
+
+/*
+ * Asks the emulation to stop. Nothing is torn down right away, as the
+ * caller usually still holds on to one of the subsystem contexts.
+ */
+pub fn request() {
+    info!("shutdown requested");
+    SHUTDOWN_REQUESTED.with(|requested| requested.set(true));
+}
+
+pub fn is_requested() -> bool {
+    SHUTDOWN_REQUESTED.with(|requested| requested.get())
+}
+
+/*
+ * Must only be called from a hostcall boundary where none of the
+ * subsystem contexts are borrowed. The generated 68k loop never returns
+ * on its own, so this is where we leave it.
+ */
+pub fn check() {
+    if is_requested() {
+        run();
+    }
+}
+
+pub fn run() -> ! {
+    let hooks = SHUTDOWN_HOOKS.with(|hooks| hooks.replace(Vec::new()));
+
+    for hook in hooks {
+        hook();
+    }
+
+    crate::uninit();
+
+    info!("bye!");
+    exit(0);
+}
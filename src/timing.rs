@@ -0,0 +1,156 @@
+use std::cell::RefCell;
+use std::thread::sleep;
+use std::time::{ Duration, Instant };
+use std::sync::atomic::{ AtomicU32, Ordering };
+
+use sdl2::TimerSubsystem;
+use sdl2::timer::Timer;
+
+use crate::GameConfig;
+
+/* frontier expects a 50Hz VBL, like the ST it was written for */
+static VBL_PERIOD_MS: u32 = 20;
+
+/* if we are more than this many frames behind we stop catching up */
+static MAX_FRAME_LAG: u32 = 5;
+
+/* VBLs raised by the SDL timer thread, consumed by the emulation thread */
+static VBL_PENDING: AtomicU32 = AtomicU32::new(0);
+
+thread_local! {
+    static FRAME_SCHEDULER: RefCell<Option<FrameScheduler>> = RefCell::default();
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameReport {
+    pub frame: u64,
+    pub lateness: Duration,
+    pub dropped: u32,
+}
+
+enum VblSource {
+    Clock { next_vbl: Instant },
+    Timer,
+}
+
+pub struct FrameScheduler {
+    period: Duration,
+    source: VblSource,
+    frame: u64,
+    last_report: FrameReport,
+}
+
+impl FrameScheduler {
+    fn new(use_timer: bool) -> Self {
+        let period = Duration::from_millis(VBL_PERIOD_MS as u64);
+        let source = if use_timer {
+            VblSource::Timer
+        } else {
+            VblSource::Clock { next_vbl: Instant::now() + period }
+        };
+
+        Self { period, source, frame: 0, last_report: FrameReport::default() }
+    }
+
+    /*
+     * Blocks until the next VBL is due. A frame that took too long is not
+     * waited for, so the following frames can catch up again. If we fall
+     * too far behind the missed VBLs are dropped instead.
+     */
+    fn wait(&mut self) -> FrameReport {
+        let period = self.period;
+
+        let (lateness, dropped) = match self.source {
+            VblSource::Clock { ref mut next_vbl } => {
+                let now = Instant::now();
+
+                if now < *next_vbl {
+                    sleep(*next_vbl - now);
+                }
+
+                let now = Instant::now();
+                let lateness = now.saturating_duration_since(*next_vbl);
+                let mut dropped = 0;
+
+                *next_vbl += period;
+
+                if lateness > period * MAX_FRAME_LAG {
+                    dropped = (lateness.as_micros() / period.as_micros()) as u32;
+                    *next_vbl = now + period;
+                }
+
+                (lateness, dropped)
+            },
+
+            VblSource::Timer => {
+                let mut pending = VBL_PENDING.swap(0, Ordering::AcqRel);
+
+                while pending == 0 {
+                    sleep(Duration::from_millis(1));
+                    pending = VBL_PENDING.swap(0, Ordering::AcqRel);
+                }
+
+                /* only one VBL is delivered per frame, the rest is dropped */
+                (period * (pending - 1), pending - 1)
+            },
+        };
+
+        self.frame += 1;
+        self.last_report = FrameReport { frame: self.frame, lateness, dropped };
+
+        self.last_report
+    }
+}
+
+impl FrameScheduler {
+    fn resync(&mut self) {
+        if let VblSource::Clock { ref mut next_vbl } = self.source {
+            *next_vbl = Instant::now() + self.period;
+        }
+
+        VBL_PENDING.store(0, Ordering::Release);
+    }
+}
+
+pub fn init(config: &GameConfig) {
+    let scheduler = FrameScheduler::new(config.vbl_timer);
+
+    FRAME_SCHEDULER.with(|ref_cell| {
+        *ref_cell.borrow_mut() = Some(scheduler);
+    });
+}
+
+/*
+ * Raises a pending VBL every 20ms from SDL's timer thread, like the
+ * vbl_callback of the C version did. The timer stops when it is dropped.
+ */
+pub fn add_vbl_timer(timer: &TimerSubsystem) -> Timer<'_, 'static> {
+    timer.add_timer(VBL_PERIOD_MS, Box::new(|| {
+        VBL_PENDING.fetch_add(1, Ordering::AcqRel);
+        VBL_PERIOD_MS
+    }))
+}
+
+/* the emulated time between two VBLs */
+pub fn vbl_period() -> Duration {
+    Duration::from_millis(VBL_PERIOD_MS as u64)
+}
+
+pub fn wait_for_vbl() {
+    with_static_ref_option! {
+        let scheduler = { FRAME_SCHEDULER } or { sleep(Duration::from_millis(VBL_PERIOD_MS as u64)); };
+        let report = scheduler.wait();
+
+        /* only complain about frames that missed a whole VBL */
+        if report.lateness.as_millis() >= VBL_PERIOD_MS as u128 || report.dropped > 0 {
+            debug!("frame {} late by {}ms, dropped {} VBLs", report.frame, report.lateness.as_millis(), report.dropped);
+        }
+    };
+}
+
+/* forget about missed VBLs, e.g. after the emulation has been paused */
+pub fn resync() {
+    with_static_ref_option!([FRAME_SCHEDULER => scheduler] {
+        scheduler.resync();
+    } or {});
+}
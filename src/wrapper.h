@@ -85,6 +85,7 @@ extern void draw_3dview (struct ZNode *node);
 extern void draw_control_panel();
 extern void set_gl_clear_col(int rgb);
 extern void set_main_viewport();
+extern void draw_osd_string(int xpos, int ypos, const char *str);
 #include "screen.h"
 
 #include "input.h"